@@ -1,18 +1,22 @@
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
 use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
 
-use tokio::io::AsyncBufReadExt;
-use tokio_stream::wrappers::LinesStream;
+use tokio_util::codec::{FramedRead, LinesCodec, LinesCodecError};
 use tokio_util::io::StreamReader;
 
 use futures_util::stream::{Stream, StreamExt, TryStreamExt};
 
 use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use serde_json::Value;
 
 use chessboard::Color;
 
-pub type Response<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+pub type Response<T> = Result<T, LichessError>;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ClockSettings {
@@ -23,354 +27,4785 @@ pub struct ClockSettings {
     pub increment: u32,
 }
 
+/// What to include when exporting a game, see `Lichess::export_game`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportOptions {
+    pub moves: bool,
+    pub tags: bool,
+    pub clocks: bool,
+    pub evals: bool,
+    pub opening: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> ExportOptions {
+        ExportOptions {
+            moves: true,
+            tags: true,
+            clocks: true,
+            evals: true,
+            opening: true,
+        }
+    }
+}
+
+impl ExportOptions {
+    fn query(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("moves", self.moves.to_string()),
+            ("tags", self.tags.to_string()),
+            ("clocks", self.clocks.to_string()),
+            ("evals", self.evals.to_string()),
+            ("opening", self.opening.to_string()),
+        ]
+    }
+}
+
+/// Filters for `Lichess::export_user_games`; `None` leaves it up to Lichess
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserGamesOptions {
+    pub max: Option<u32>,
+    pub rated: Option<bool>,
+    pub perf_type: Option<String>,
+    /// Milliseconds since the epoch
+    pub since: Option<u64>,
+    /// Milliseconds since the epoch
+    pub until: Option<u64>,
+    pub analysed: Option<bool>,
+}
+
+impl UserGamesOptions {
+    fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = Vec::new();
+
+        if let Some(max) = self.max {
+            query.push(("max", max.to_string()));
+        }
+
+        if let Some(rated) = self.rated {
+            query.push(("rated", rated.to_string()));
+        }
+
+        if let Some(perf_type) = &self.perf_type {
+            query.push(("perfType", perf_type.clone()));
+        }
+
+        if let Some(since) = self.since {
+            query.push(("since", since.to_string()));
+        }
+
+        if let Some(until) = self.until {
+            query.push(("until", until.to_string()));
+        }
+
+        if let Some(analysed) = self.analysed {
+            query.push(("analysed", analysed.to_string()));
+        }
+
+        query
+    }
+}
+
+/// A challenge to another player, see `Lichess::challenge`; anything left unset
+/// is up to Lichess
+#[derive(Debug, Clone, Default)]
+pub struct ChallengeRequest {
+    rated: bool,
+    color: Option<Color>,
+    clock: Option<ClockSettings>,
+    variant: Option<String>,
+    fen: Option<String>,
+    keep_alive_stream: bool,
+    rules: Vec<String>,
+    accept_by_token: Option<String>,
+    message: Option<String>,
+}
+
+impl ChallengeRequest {
+    pub fn new() -> ChallengeRequest {
+        ChallengeRequest::default()
+    }
+
+    pub fn rated(mut self, rated: bool) -> ChallengeRequest {
+        self.rated = rated;
+        self
+    }
+
+    /// Which color you play; random if unset
+    pub fn color(mut self, color: Color) -> ChallengeRequest {
+        self.color = Some(color);
+        self
+    }
+
+    /// The time control; unlimited if unset
+    pub fn clock(mut self, clock: ClockSettings) -> ChallengeRequest {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// The variant key, e.g. `chess960` or `atomic`
+    pub fn variant(mut self, variant: impl Into<String>) -> ChallengeRequest {
+        self.variant = Some(variant.into());
+        self
+    }
+
+    /// Start from a custom position
+    pub fn fen(mut self, fen: impl Into<String>) -> ChallengeRequest {
+        self.fen = Some(fen.into());
+        self
+    }
+
+    /// Keep the challenge alive only as long as the request is open; the
+    /// request then doesn't return until the challenge is answered
+    pub fn keep_alive_stream(mut self, keep_alive: bool) -> ChallengeRequest {
+        self.keep_alive_stream = keep_alive;
+        self
+    }
+
+    /// Add a game rule, e.g. `noAbort`, `noRematch`, `noGiveTime`, `noClaimWin`
+    pub fn rule(mut self, rule: impl Into<String>) -> ChallengeRequest {
+        self.rules.push(rule.into());
+        self
+    }
+
+    /// Accept the challenge right away on the opponent's behalf, with their
+    /// `challenge:write` token
+    pub fn accept_by_token(mut self, token: impl Into<String>) -> ChallengeRequest {
+        self.accept_by_token = Some(token.into());
+        self
+    }
+
+    /// With `accept_by_token`, a message sent to both players when the game
+    /// starts; may contain `{game}`, which becomes the game url
+    pub fn message(mut self, message: impl Into<String>) -> ChallengeRequest {
+        self.message = Some(message.into());
+        self
+    }
+
+    fn body(&self) -> Response<Vec<(&'static str, String)>> {
+        let mut body = vec![("rated", self.rated.to_string())];
+
+        body.push(match self.color {
+            Some(Color::White) => ("color", String::from("white")),
+            Some(Color::Black) => ("color", String::from("black")),
+            None => ("color", String::from("random")),
+        });
+
+        body.extend(Lichess::encode_clock(
+            self.clock.as_ref(),
+            ClockStyle::Challenge,
+        )?);
+
+        if let Some(variant) = &self.variant {
+            body.push(("variant", variant.clone()));
+        }
+
+        if let Some(fen) = &self.fen {
+            body.push(("fen", fen.clone()));
+        }
+
+        if self.keep_alive_stream {
+            body.push(("keepAliveStream", String::from("true")));
+        }
+
+        if !self.rules.is_empty() {
+            body.push(("rules", self.rules.join(",")));
+        }
+
+        if let Some(token) = &self.accept_by_token {
+            body.push(("acceptByToken", token.clone()));
+        }
+
+        if let Some(message) = &self.message {
+            body.push(("message", message.clone()));
+        }
+
+        Ok(body)
+    }
+}
+
+/// Which set of field names an endpoint uses for its clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClockStyle {
+    /// `clock.limit`/`clock.increment` in seconds, used by `challenge/`
+    Challenge,
+    /// `time` in minutes and `increment` in seconds, used by `board/seek`
+    Seek,
+}
+
+/// Whether to play through the `board/` or the `bot/` endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlayAs {
+    Board,
+    Bot,
+}
+
+impl PlayAs {
+    fn prefix(&self) -> &'static str {
+        match self {
+            PlayAs::Board => "board",
+            PlayAs::Bot => "bot",
+        }
+    }
+
+    fn scope(&self) -> &'static str {
+        match self {
+            PlayAs::Board => "board:play",
+            PlayAs::Bot => "bot:play",
+        }
+    }
+}
+
+/// Reasons Lichess accepts when declining a challenge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeDeclineReason {
+    Generic,
+    Later,
+    TooFast,
+    TooSlow,
+    TimeControl,
+    Rated,
+    Casual,
+    Standard,
+    Variant,
+    NoBot,
+    OnlyBot,
+}
+
+impl ChallengeDeclineReason {
+    /// The string Lichess expects in the `reason` field
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChallengeDeclineReason::Generic => "generic",
+            ChallengeDeclineReason::Later => "later",
+            ChallengeDeclineReason::TooFast => "tooFast",
+            ChallengeDeclineReason::TooSlow => "tooSlow",
+            ChallengeDeclineReason::TimeControl => "timeControl",
+            ChallengeDeclineReason::Rated => "rated",
+            ChallengeDeclineReason::Casual => "casual",
+            ChallengeDeclineReason::Standard => "standard",
+            ChallengeDeclineReason::Variant => "variant",
+            ChallengeDeclineReason::NoBot => "noBot",
+            ChallengeDeclineReason::OnlyBot => "onlyBot",
+        }
+    }
+}
+
+impl fmt::Display for ChallengeDeclineReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Which chat room of a game to post in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatRoom {
+    Player,
+    Spectator,
+}
+
+impl ChatRoom {
+    /// The string Lichess expects in the `room` field
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatRoom::Player => "player",
+            ChatRoom::Spectator => "spectator",
+        }
+    }
+}
+
+/// Deserialize `"white"`/`"black"` into a `Color`
+fn de_color<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    de_opt_color(deserializer)?
+        .ok_or_else(|| serde::de::Error::custom("expected `white` or `black`"))
+}
+
+/// Deserialize `"white"`/`"black"` into a `Color`, and `"random"` or null into `None`
+fn de_opt_color<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Color>, D::Error> {
+    match Option::<String>::deserialize(deserializer)?.as_deref() {
+        Some("white") => Ok(Some(Color::White)),
+        Some("black") => Ok(Some(Color::Black)),
+        Some("random") | None => Ok(None),
+        Some(other) => Err(serde::de::Error::unknown_variant(
+            other,
+            &["white", "black", "random"],
+        )),
+    }
+}
+
+/// Deserialize a comma-separated list of scopes
+fn de_scopes<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let scopes = String::deserialize(deserializer)?;
+
+    Ok(scopes
+        .split(',')
+        .map(str::trim)
+        .filter(|scope| !scope.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Deserialize `[year, month, day, rating]` points, turning the zero-based
+/// month Lichess sends into a one-based one
+fn de_rating_points<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(i32, i32, i32, i32)>, D::Error> {
+    let points = Vec::<(i32, i32, i32, i32)>::deserialize(deserializer)?;
+
+    Ok(points
+        .into_iter()
+        .map(|(year, month, day, rating)| (year, month + 1, day, rating))
+        .collect())
+}
+
+/// What a token is allowed to do, see `Lichess::token_info`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenInfo {
+    pub user_id: String,
+    #[serde(deserialize_with = "de_scopes")]
+    pub scopes: Vec<String>,
+    /// Milliseconds since the epoch, or `None` if it never expires
+    pub expires: Option<i64>,
+}
+
+impl TokenInfo {
+    /// Whether the token was granted `scope`, e.g. `board:play`
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+/// A token obtained through OAuth, see `Lichess::exchange_code`
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAuthToken {
+    pub access_token: String,
+    pub token_type: String,
+    /// Seconds until the token expires
+    pub expires_in: Option<u64>,
+}
+
+/// Your account, as returned by `Lichess::account_typed`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Account {
+    pub id: String,
+    pub username: String,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub online: bool,
+    #[serde(default)]
+    pub perfs: Perfs,
+    pub created_at: Option<u64>,
+    pub playing: Option<String>,
+}
+
+impl Account {
+    /// Your rating in a perf, e.g. `"blitz"` or `"racingKings"`
+    pub fn rating(&self, perf: &str) -> Option<i32> {
+        self.perfs.get(perf).map(|p| p.rating)
+    }
+}
+
+/// A player's rating in one speed or variant
+#[derive(Debug, Clone, Deserialize)]
+pub struct Perf {
+    #[serde(default)]
+    pub games: u32,
+    pub rating: i32,
+    #[serde(default)]
+    pub rd: i32,
+    #[serde(default)]
+    pub prog: i32,
+    /// Whether the rating is still provisional
+    #[serde(default)]
+    pub prov: bool,
+}
+
+/// A player's ratings, one per speed or variant they have played
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Perfs {
+    pub ultra_bullet: Option<Perf>,
+    pub bullet: Option<Perf>,
+    pub blitz: Option<Perf>,
+    pub rapid: Option<Perf>,
+    pub classical: Option<Perf>,
+    pub correspondence: Option<Perf>,
+    pub chess960: Option<Perf>,
+    pub king_of_the_hill: Option<Perf>,
+    pub three_check: Option<Perf>,
+    pub antichess: Option<Perf>,
+    pub atomic: Option<Perf>,
+    pub horde: Option<Perf>,
+    pub racing_kings: Option<Perf>,
+    pub crazyhouse: Option<Perf>,
+    pub puzzle: Option<Perf>,
+}
+
+impl Perfs {
+    /// Look a perf up by its Lichess name, e.g. `"ultraBullet"`
+    pub fn get(&self, perf: &str) -> Option<&Perf> {
+        match perf {
+            "ultraBullet" => self.ultra_bullet.as_ref(),
+            "bullet" => self.bullet.as_ref(),
+            "blitz" => self.blitz.as_ref(),
+            "rapid" => self.rapid.as_ref(),
+            "classical" => self.classical.as_ref(),
+            "correspondence" => self.correspondence.as_ref(),
+            "chess960" => self.chess960.as_ref(),
+            "kingOfTheHill" => self.king_of_the_hill.as_ref(),
+            "threeCheck" => self.three_check.as_ref(),
+            "antichess" => self.antichess.as_ref(),
+            "atomic" => self.atomic.as_ref(),
+            "horde" => self.horde.as_ref(),
+            "racingKings" => self.racing_kings.as_ref(),
+            "crazyhouse" => self.crazyhouse.as_ref(),
+            "puzzle" => self.puzzle.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// A challenge or game you created, as returned by `Lichess::ai_typed`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Challenge {
+    pub id: String,
+    pub url: Option<String>,
+    #[serde(default, deserialize_with = "de_opt_color")]
+    pub color: Option<Color>,
+    pub speed: Option<String>,
+    #[serde(default)]
+    pub status: Value,
+}
+
+/// A challenge anyone can accept, see `Lichess::open_challenge`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenChallenge {
+    pub id: String,
+    pub url: String,
+    /// Joins the game as white
+    pub url_white: String,
+    /// Joins the game as black
+    pub url_black: String,
+}
+
+/// Another player's public profile, as returned by `Lichess::user`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    #[serde(default)]
+    pub perfs: Perfs,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub online: bool,
+    pub playing: Option<String>,
+    pub created_at: Option<u64>,
+    pub seen_at: Option<u64>,
+}
+
+impl User {
+    /// The player's rating in a perf, e.g. `"blitz"` or `"racingKings"`
+    pub fn rating(&self, perf: &str) -> Option<i32> {
+        self.perfs.get(perf).map(|p| p.rating)
+    }
+}
+
+/// Whether a player is online or playing, see `Lichess::users_status`
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserStatus {
+    pub id: String,
+    pub name: String,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub online: bool,
+    #[serde(default)]
+    pub playing: bool,
+}
+
+/// A player on a leaderboard, see `Lichess::leaderboard`
+#[derive(Debug, Clone)]
+pub struct LeaderboardEntry {
+    pub id: String,
+    pub username: String,
+    pub title: Option<String>,
+    /// The rating in the leaderboard's perf
+    pub rating: i32,
+}
+
+#[derive(Deserialize)]
+struct LeaderboardUser {
+    id: String,
+    username: String,
+    title: Option<String>,
+    #[serde(default)]
+    perfs: Perfs,
+}
+
+/// A player's rating over time in one perf, see `Lichess::rating_history`
+#[derive(Debug, Clone, Deserialize)]
+pub struct RatingHistory {
+    /// The perf's display name, e.g. `Bullet` or `Racing Kings`
+    pub name: String,
+    /// `(year, month, day, rating)`, with months counted from 1
+    #[serde(deserialize_with = "de_rating_points")]
+    pub points: Vec<(i32, i32, i32, i32)>,
+}
+
+/// A game uploaded with `Lichess::import_game`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportedGame {
+    pub id: String,
+    pub url: String,
+}
+
+/// A scheduled bulk pairing, see `Lichess::list_bulk_pairings`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkPairing {
+    pub id: String,
+    #[serde(default)]
+    pub games: Vec<BulkPairingGame>,
+    #[serde(default)]
+    pub rated: bool,
+    /// When the pairing was scheduled, in milliseconds since the epoch
+    pub scheduled_at: u64,
+    /// When the games will be created
+    pub pair_at: Option<u64>,
+    /// When the games were created, if they have been
+    pub paired_at: Option<u64>,
+    pub start_clocks_at: Option<u64>,
+}
+
+/// One game of a `BulkPairing`
+#[derive(Debug, Clone, Deserialize)]
+pub struct BulkPairingGame {
+    pub id: String,
+    pub white: String,
+    pub black: String,
+}
+
+/// The head-to-head record of two players, see `Lichess::crosstable`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Crosstable {
+    /// Each player's score against the other, by user id
+    pub users: HashMap<String, f32>,
+    pub nb_games: u32,
+    /// The record of their current match, if asked for and they're playing
+    pub matchup: Option<Matchup>,
+}
+
+/// The record of two players' current match, see `Crosstable::matchup`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Matchup {
+    pub users: HashMap<String, f32>,
+    pub nb_games: u32,
+}
+
+/// A cached engine evaluation, see `Lichess::cloud_eval`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CloudEval {
+    pub fen: String,
+    pub depth: u32,
+    pub knodes: u64,
+    pub pvs: Vec<Pv>,
+}
+
+/// One principal variation of a `CloudEval`; exactly one of `cp` or `mate` is set
+#[derive(Debug, Clone, Deserialize)]
+pub struct Pv {
+    pub moves: String,
+    pub cp: Option<i32>,
+    pub mate: Option<i32>,
+}
+
+/// Opening explorer statistics for a position, see `Lichess::explorer_masters`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExplorerResult {
+    pub white: u64,
+    pub draws: u64,
+    pub black: u64,
+    pub moves: Vec<ExplorerMove>,
+    #[serde(default)]
+    pub top_games: Vec<ExplorerGame>,
+    pub opening: Option<Opening>,
+}
+
+/// A move played from an explorer position, with its results
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExplorerMove {
+    pub uci: String,
+    pub san: String,
+    pub white: u64,
+    pub draws: u64,
+    pub black: u64,
+    pub average_rating: Option<u32>,
+}
+
+/// A notable game that reached an explorer position
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExplorerGame {
+    pub id: String,
+    /// `None` for a draw, or a game that hasn't ended
+    #[serde(default, deserialize_with = "de_opt_color")]
+    pub winner: Option<Color>,
+    pub white: ExplorerPlayer,
+    pub black: ExplorerPlayer,
+    pub year: Option<u32>,
+}
+
+/// A player in an `ExplorerGame`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExplorerPlayer {
+    pub name: String,
+    pub rating: Option<u32>,
+}
+
+/// A named opening
+#[derive(Debug, Clone, Deserialize)]
+pub struct Opening {
+    pub eco: String,
+    pub name: String,
+}
+
+/// A tablebase verdict for a position, see `Lichess::tablebase`
+#[derive(Debug, Clone, Deserialize)]
+pub struct TablebaseResult {
+    /// `win`, `loss`, `draw`, `cursed-win`, `blessed-loss`, etc.
+    pub category: String,
+    pub dtz: Option<i32>,
+    pub dtm: Option<i32>,
+    pub moves: Vec<TablebaseMove>,
+}
+
+/// A move from a tablebase position, and the verdict it leads to
+#[derive(Debug, Clone, Deserialize)]
+pub struct TablebaseMove {
+    pub uci: String,
+    pub san: String,
+    pub category: String,
+    pub dtz: Option<i32>,
+    pub dtm: Option<i32>,
+}
+
+/// A player's final place in an arena tournament, see `Lichess::arena_results`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ArenaStanding {
+    pub rank: u32,
+    pub username: String,
+    pub score: u32,
+    pub rating: u32,
+    pub performance: Option<u32>,
+}
+
+/// A puzzle and the game it comes from, see `Lichess::puzzle`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Puzzle {
+    pub game: PuzzleGame,
+    pub puzzle: PuzzleInfo,
+}
+
+/// The game a `Puzzle` was taken from
+#[derive(Debug, Clone, Deserialize)]
+pub struct PuzzleGame {
+    pub id: String,
+    pub pgn: String,
+    pub players: Vec<PuzzlePlayer>,
+}
+
+/// A player in a `PuzzleGame`
+#[derive(Debug, Clone, Deserialize)]
+pub struct PuzzlePlayer {
+    pub name: String,
+    #[serde(deserialize_with = "de_color")]
+    pub color: Color,
+    pub rating: Option<u32>,
+}
+
+/// The puzzle itself; `solution` is in UCI, starting after `initial_ply`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PuzzleInfo {
+    pub id: String,
+    pub rating: u32,
+    pub plays: u32,
+    pub initial_ply: u32,
+    pub solution: Vec<String>,
+    pub themes: Vec<String>,
+}
+
+/// An event from a TV channel's feed, see `Lichess::tv_events`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "t", content = "d", rename_all = "camelCase")]
+pub enum TvEvent {
+    /// A new game is being featured
+    Featured(TvFeatured),
+    /// A move was played in the featured game
+    Fen(TvFen),
+}
+
+/// The game a TV channel just switched to
+#[derive(Debug, Clone, Deserialize)]
+pub struct TvFeatured {
+    pub id: String,
+    #[serde(deserialize_with = "de_color")]
+    pub orientation: Color,
+    #[serde(default)]
+    pub players: Vec<Value>,
+    pub fen: String,
+}
+
+/// The position after a move in the featured game, with the clocks in seconds
+#[derive(Debug, Clone, Deserialize)]
+pub struct TvFen {
+    pub fen: String,
+    /// The last move, in UCI
+    pub lm: Option<String>,
+    pub wc: Option<u64>,
+    pub bc: Option<u64>,
+}
+
+/// A player's final place in a Swiss tournament, see `Lichess::swiss_results`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SwissStanding {
+    pub rank: u32,
+    pub username: String,
+    pub points: f32,
+    pub tie_break: f32,
+    pub rating: u32,
+    pub performance: Option<u32>,
+}
+
+/// The opponent in an `OngoingGame`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Opponent {
+    pub id: Option<String>,
+    pub username: String,
+    pub rating: Option<u32>,
+    pub ai: Option<u8>,
+}
+
+/// A game you're currently playing, as returned by `Lichess::ongoing_games`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OngoingGame {
+    pub game_id: String,
+    pub full_id: String,
+    #[serde(deserialize_with = "de_color")]
+    pub color: Color,
+    pub fen: String,
+    pub is_my_turn: bool,
+    #[serde(default)]
+    pub last_move: String,
+    pub opponent: Opponent,
+    pub seconds_left: Option<u64>,
+}
+
+/// An event from the user event stream, see `Lichess::typed_events`
+#[derive(Debug, Clone)]
+pub enum IncomingEvent {
+    GameStart(GameEventInfo),
+    GameFinish(GameEventInfo),
+    Challenge(ChallengeInfo),
+    ChallengeCanceled(ChallengeInfo),
+    ChallengeDeclined(ChallengeInfo),
+    /// An event this crate doesn't know how to parse (yet)
+    Other(Value),
+    /// Not from Lichess: `Lichess::events_resilient` lost the stream and
+    /// reconnected, so events may have been missed
+    Reconnected,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum KnownIncomingEvent {
+    GameStart { game: GameEventInfo },
+    GameFinish { game: GameEventInfo },
+    Challenge { challenge: ChallengeInfo },
+    ChallengeCanceled { challenge: ChallengeInfo },
+    ChallengeDeclined { challenge: ChallengeInfo },
+}
+
+impl IncomingEvent {
+    /// The id of the game or challenge the event is about, if any; a challenge's
+    /// id is also the id of the game it turns into
+    pub fn game_id(&self) -> Option<&str> {
+        match self {
+            IncomingEvent::GameStart(game) | IncomingEvent::GameFinish(game) => {
+                Some(game.game_id.as_str())
+            }
+            IncomingEvent::Challenge(challenge)
+            | IncomingEvent::ChallengeCanceled(challenge)
+            | IncomingEvent::ChallengeDeclined(challenge) => Some(challenge.id.as_str()),
+            IncomingEvent::Other(_) | IncomingEvent::Reconnected => None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for IncomingEvent {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+
+        Ok(match KnownIncomingEvent::deserialize(&value) {
+            Ok(KnownIncomingEvent::GameStart { game }) => IncomingEvent::GameStart(game),
+            Ok(KnownIncomingEvent::GameFinish { game }) => IncomingEvent::GameFinish(game),
+            Ok(KnownIncomingEvent::Challenge { challenge }) => IncomingEvent::Challenge(challenge),
+            Ok(KnownIncomingEvent::ChallengeCanceled { challenge }) => {
+                IncomingEvent::ChallengeCanceled(challenge)
+            }
+            Ok(KnownIncomingEvent::ChallengeDeclined { challenge }) => {
+                IncomingEvent::ChallengeDeclined(challenge)
+            }
+            Err(_) => IncomingEvent::Other(value),
+        })
+    }
+}
+
+/// The game attached to a `gameStart` or `gameFinish` event
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameEventInfo {
+    pub game_id: String,
+    pub full_id: Option<String>,
+    #[serde(deserialize_with = "de_color")]
+    pub color: Color,
+    pub fen: String,
+    #[serde(default)]
+    pub is_my_turn: bool,
+    #[serde(default)]
+    pub last_move: String,
+    pub opponent: Opponent,
+    #[serde(default)]
+    pub rated: bool,
+    pub speed: Option<String>,
+    pub source: Option<String>,
+    /// `None` for a draw, or a game that hasn't ended
+    #[serde(default, deserialize_with = "de_opt_color")]
+    pub winner: Option<Color>,
+}
+
+/// The challenge attached to a `challenge*` event
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengeInfo {
+    pub id: String,
+    pub url: String,
+    pub status: String,
+    pub challenger: Option<ChallengeUser>,
+    pub dest_user: Option<ChallengeUser>,
+    pub variant: Variant,
+    #[serde(default)]
+    pub rated: bool,
+    pub speed: String,
+    #[serde(default)]
+    pub time_control: Value,
+    /// The color the challenger asked for; `None` if random
+    #[serde(default, deserialize_with = "de_opt_color")]
+    pub color: Option<Color>,
+    pub decline_reason: Option<String>,
+}
+
+/// A player taking part in a challenge
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChallengeUser {
+    pub id: String,
+    pub name: String,
+    pub title: Option<String>,
+    pub rating: Option<u32>,
+}
+
+/// An event from a board game stream, see `Lichess::board_events`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BoardEvent {
+    GameFull(GameFull),
+    GameState(GameState),
+    ChatLine(ChatLine),
+    #[serde(rename_all = "camelCase")]
+    OpponentGone {
+        gone: bool,
+        claim_win_in_seconds: Option<u32>,
+    },
+}
+
+/// The full state of a game, sent once when a board stream opens
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameFull {
+    pub id: String,
+    pub variant: Variant,
+    pub clock: Option<GameClock>,
+    pub speed: String,
+    #[serde(default)]
+    pub rated: bool,
+    pub white: GamePlayer,
+    pub black: GamePlayer,
+    pub initial_fen: String,
+    pub state: GameState,
+}
+
+/// The current state of a game, sent after every move
+#[derive(Debug, Clone, Deserialize)]
+pub struct GameState {
+    pub moves: String,
+    pub wtime: u64,
+    pub btime: u64,
+    pub winc: u64,
+    pub binc: u64,
+    pub status: String,
+    /// `None` for a draw, or a game that hasn't ended
+    #[serde(default, deserialize_with = "de_opt_color")]
+    pub winner: Option<Color>,
+}
+
+/// A chat message sent during a game
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatLine {
+    pub username: String,
+    pub text: String,
+    pub room: String,
+}
+
+/// A message from a game's chat history, see `Lichess::fetch_chat`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatMessage {
+    #[serde(alias = "user")]
+    pub username: String,
+    pub text: String,
+    pub room: Option<String>,
+}
+
+/// A game variant, e.g. `standard` or `chess960`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Variant {
+    pub key: String,
+    pub name: String,
+}
+
+/// A game's starting clock, in milliseconds
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct GameClock {
+    pub initial: u64,
+    pub increment: u64,
+}
+
+/// One side of a game; either a player or the AI
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GamePlayer {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub title: Option<String>,
+    pub rating: Option<u32>,
+    pub ai_level: Option<u8>,
+}
+
 #[derive(Debug)]
 pub struct ApiError {
     code: u16,
     msg: Value,
 }
 
-impl ApiError {
-    pub fn new(code: u16, msg: Value) -> ApiError {
-        ApiError { code, msg }
+impl ApiError {
+    pub fn new(code: u16, msg: Value) -> ApiError {
+        ApiError { code, msg }
+    }
+
+    /// The HTTP status code Lichess returned
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// The error body Lichess returned, or `Value::Null` if it was empty
+    pub fn message(&self) -> &Value {
+        &self.msg
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP {}: {}", self.code, self.msg)
+    }
+}
+
+impl Error for ApiError {}
+
+/// Why Lichess refused a move, see `LichessError::Move`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveError {
+    /// It's the opponent's move; re-sync with the game state
+    NotYourTurn,
+    /// The move isn't legal in the current position
+    IllegalMove,
+    /// The game has already ended
+    GameOver,
+    /// Anything else, with Lichess' message
+    Other(String),
+}
+
+impl MoveError {
+    /// Sort an error body from a move endpoint, e.g.
+    /// `{"error": "Not your turn, or game already over"}`
+    fn from_body(body: &Value) -> MoveError {
+        let msg = match body["error"].as_str().or(body.as_str()) {
+            Some(msg) => msg.to_string(),
+            None => body.to_string(),
+        };
+        let lower = msg.to_lowercase();
+
+        // lichess reports both in one message, and it's usually the turn
+        if lower.contains("not your turn") {
+            MoveError::NotYourTurn
+        } else if lower.contains("game") && (lower.contains("over") || lower.contains("finished")) {
+            MoveError::GameOver
+        } else if ["illegal", "unknownmove", "invalid", "cannot move"]
+            .iter()
+            .any(|m| lower.contains(m))
+        {
+            MoveError::IllegalMove
+        } else {
+            MoveError::Other(msg)
+        }
+    }
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::NotYourTurn => f.write_str("not your turn"),
+            MoveError::IllegalMove => f.write_str("illegal move"),
+            MoveError::GameOver => f.write_str("the game is over"),
+            MoveError::Other(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl Error for MoveError {}
+
+/// Everything that can go wrong talking to Lichess
+#[derive(Debug)]
+pub enum LichessError {
+    /// Lichess answered with a bad status code
+    Http(ApiError),
+    /// The request couldn't be sent, or its response couldn't be read
+    Transport(reqwest::Error),
+    /// A stream broke partway through
+    Io(std::io::Error),
+    /// A response wasn't the json we expected
+    Json(serde_json::Error),
+    /// Lichess answered successfully, but without the fields we expected
+    Unexpected { endpoint: &'static str, body: Value },
+    /// Still rate limited (HTTP 429) after any retries
+    RateLimited { retry_after: Option<Duration> },
+    /// A response was bigger than `Lichess::with_max_body_size` allows
+    BodyTooLarge { limit: usize },
+    /// A streamed line was longer than `LichessBuilder::max_line_length` allows
+    LineTooLong { limit: usize },
+    /// Lichess answered successfully, but with an `error` field
+    Lichess(Value),
+    /// Looked up a user that doesn't exist
+    UserNotFound { username: String },
+    /// Probed the tablebase with more pieces than it covers
+    TooManyPieces { pieces: usize },
+    /// The token lacks the scope a method needs, see `LichessBuilder::preflight_scopes`
+    MissingScope(&'static str),
+    /// The client's token is invalid or has expired, see `Lichess::token_info`
+    InvalidToken,
+    /// An argument Lichess won't accept, usually caught before sending anything
+    InvalidInput(String),
+    /// Lichess refused a move
+    Move(MoveError),
+}
+
+impl LichessError {
+    /// The HTTP status code of an error, if it came from Lichess
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            LichessError::Http(e) => Some(e.code()),
+            LichessError::RateLimited { .. } => Some(429),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LichessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LichessError::Http(e) => e.fmt(f),
+            LichessError::Transport(e) => write!(f, "request failed: {e}"),
+            LichessError::Io(e) => write!(f, "stream failed: {e}"),
+            LichessError::Json(e) => write!(f, "invalid json: {e}"),
+            LichessError::Unexpected { endpoint, body } => {
+                write!(f, "unexpected response from `{endpoint}`: {body}")
+            }
+            LichessError::RateLimited {
+                retry_after: Some(wait),
+            } => write!(f, "rate limited, retry after {}s", wait.as_secs()),
+            LichessError::RateLimited { retry_after: None } => f.write_str("rate limited"),
+            LichessError::BodyTooLarge { limit } => {
+                write!(f, "response is bigger than the {limit} byte limit")
+            }
+            LichessError::LineTooLong { limit } => {
+                write!(f, "stream line is longer than the {limit} byte limit")
+            }
+            LichessError::Lichess(err) => write!(f, "lichess error: {err}"),
+            LichessError::UserNotFound { username } => write!(f, "user not found: {username}"),
+            LichessError::TooManyPieces { pieces } => write!(
+                f,
+                "the tablebase only covers up to {} pieces, position has {pieces}",
+                Lichess::TABLEBASE_PIECES
+            ),
+            LichessError::MissingScope(scope) => write!(f, "missing `{scope}` scope"),
+            LichessError::InvalidToken => f.write_str("the token is invalid or has expired"),
+            LichessError::InvalidInput(msg) => f.write_str(msg),
+            LichessError::Move(e) => write!(f, "move rejected: {e}"),
+        }
+    }
+}
+
+impl Error for LichessError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LichessError::Http(e) => Some(e),
+            LichessError::Transport(e) => Some(e),
+            LichessError::Io(e) => Some(e),
+            LichessError::Json(e) => Some(e),
+            LichessError::Move(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ApiError> for LichessError {
+    fn from(e: ApiError) -> LichessError {
+        LichessError::Http(e)
+    }
+}
+
+impl From<reqwest::Error> for LichessError {
+    fn from(e: reqwest::Error) -> LichessError {
+        LichessError::Transport(e)
+    }
+}
+
+impl From<std::io::Error> for LichessError {
+    fn from(e: std::io::Error) -> LichessError {
+        LichessError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for LichessError {
+    fn from(e: serde_json::Error) -> LichessError {
+        LichessError::Json(e)
+    }
+}
+
+/// One finished request, handed to `LichessBuilder::on_request`
+#[derive(Debug, Clone)]
+pub struct RequestMetric {
+    /// The path requested, e.g. `/api/account`
+    pub endpoint: String,
+    /// `None` if no response came back at all
+    pub status: Option<u16>,
+    /// Time until the response's headers arrived, not counting its body
+    pub elapsed: Duration,
+}
+
+/// Shared between clones, so every clone reports to the same place
+type RequestHook = Arc<dyn Fn(RequestMetric) + Send + Sync>;
+
+/// Configures a `Lichess` client before building it, see `Lichess::builder`
+pub struct LichessBuilder {
+    key: String,
+    base_url: String,
+    client: Option<reqwest::Client>,
+    proxy: Option<reqwest::Proxy>,
+    timeout: Option<Duration>,
+    max_retries: u32,
+    max_body_size: Option<usize>,
+    validate_fen: bool,
+    preflight_scopes: bool,
+    skip_keepalives: bool,
+    max_line_length: usize,
+    on_request: Option<RequestHook>,
+    retry_transient: bool,
+}
+
+// by hand, so that the key never ends up in logs
+impl fmt::Debug for LichessBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LichessBuilder")
+            .field("key", &"***")
+            .field("base_url", &self.base_url)
+            .field("timeout", &self.timeout)
+            .field("max_retries", &self.max_retries)
+            .field("max_body_size", &self.max_body_size)
+            .field("validate_fen", &self.validate_fen)
+            .field("preflight_scopes", &self.preflight_scopes)
+            .field("skip_keepalives", &self.skip_keepalives)
+            .field("max_line_length", &self.max_line_length)
+            .field("on_request", &self.on_request.is_some())
+            .field("retry_transient", &self.retry_transient)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LichessBuilder {
+    /// Start configuring a client with the API key in `LICHESS_TOKEN`
+    pub fn from_env() -> Result<LichessBuilder, std::env::VarError> {
+        Ok(Lichess::builder(std::env::var("LICHESS_TOKEN")?))
+    }
+
+    /// Point the client at a different Lichess instance, see `Lichess::with_base_url`
+    pub fn base_url(mut self, base_url: impl Into<String>) -> LichessBuilder {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Use an existing `reqwest::Client`, see `Lichess::with_client`;
+    /// this overrides `proxy`, which should be set on the client instead
+    pub fn client(mut self, client: reqwest::Client) -> LichessBuilder {
+        self.client = Some(client);
+        self
+    }
+
+    /// Send every request through `proxy`
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> LichessBuilder {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Time out regular requests, see `Lichess::with_timeout`
+    pub fn timeout(mut self, timeout: Duration) -> LichessBuilder {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Retry rate limited requests, see `Lichess::with_rate_limit_retries`
+    pub fn rate_limit_retries(mut self, n: u32) -> LichessBuilder {
+        self.max_retries = n;
+        self
+    }
+
+    /// Refuse responses bigger than `bytes`, see `Lichess::with_max_body_size`
+    pub fn max_body_size(mut self, bytes: usize) -> LichessBuilder {
+        self.max_body_size = Some(bytes);
+        self
+    }
+
+    /// Check the FEN of `ai`, `seek` and `challenge` before sending it, failing
+    /// with `LichessError::InvalidInput` instead of a round trip to Lichess.
+    /// Only standard FENs pass: variant extras like three-check counters or
+    /// crazyhouse pockets are rejected, so leave this off for those variants.
+    /// Off by default, so FENs are passed through as they are
+    pub fn validate_fen(mut self, validate: bool) -> LichessBuilder {
+        self.validate_fen = validate;
+        self
+    }
+
+    /// Look up the token's scopes (once, on the first request that needs one) and
+    /// fail with `LichessError::MissingScope` instead of sending requests the
+    /// token isn't allowed to make
+    pub fn preflight_scopes(mut self, preflight: bool) -> LichessBuilder {
+        self.preflight_scopes = preflight;
+        self
+    }
+
+    /// Whether ndjson streams drop `{}` keepalive lines instead of parsing
+    /// them; on by default
+    pub fn skip_keepalives(mut self, skip: bool) -> LichessBuilder {
+        self.skip_keepalives = skip;
+        self
+    }
+
+    /// Cap how long a single streamed line may be, 1 MiB by default. Past it,
+    /// `Result` streams yield `LichessError::LineTooLong` and the rest end
+    pub fn max_line_length(mut self, bytes: usize) -> LichessBuilder {
+        self.max_line_length = bytes;
+        self
+    }
+
+    /// Call `hook` after every request, streams included, e.g. to export
+    /// latency and error rates. Nothing is measured when it isn't set
+    pub fn on_request(
+        mut self,
+        hook: impl Fn(RequestMetric) + Send + Sync + 'static,
+    ) -> LichessBuilder {
+        self.on_request = Some(Arc::new(hook));
+        self
+    }
+
+    /// Retry a GET once if it couldn't connect or timed out; POSTs are never
+    /// retried, since they might not be safe to send twice
+    pub fn retry_transient(mut self, retry: bool) -> LichessBuilder {
+        self.retry_transient = retry;
+        self
+    }
+
+    /// Build the client.
+    ///
+    /// Panics if the TLS backend can't be initialized, like `reqwest::Client::new`,
+    /// or if the key can't be sent in a header, e.g. because it ends in a newline
+    pub fn build(self) -> Lichess {
+        let hclient = match self.client {
+            Some(client) => client,
+            None => {
+                let mut hclient = reqwest::Client::builder();
+
+                // rustls wins if both TLS features are on, since it's the default
+                #[cfg(feature = "rustls-tls")]
+                {
+                    hclient = hclient.use_rustls_tls();
+                }
+
+                #[cfg(all(feature = "native-tls", not(feature = "rustls-tls")))]
+                {
+                    hclient = hclient.use_native_tls();
+                }
+
+                if let Some(proxy) = self.proxy {
+                    hclient = hclient.proxy(proxy);
+                }
+
+                hclient.build().expect("failed to build the http client")
+            }
+        };
+
+        let auth = (!self.key.is_empty()).then(|| {
+            let mut auth = reqwest::header::HeaderValue::from_str(&format!("Bearer {}", self.key))
+                .expect("the API key can't be sent in a header");
+            auth.set_sensitive(true);
+            auth
+        });
+
+        Lichess {
+            key: self.key.into(),
+            auth,
+            base_url: self.base_url.trim_end_matches('/').to_string(),
+            hclient,
+            max_retries: self.max_retries,
+            timeout: self.timeout,
+            max_body_size: self.max_body_size,
+            validate_fen: self.validate_fen,
+            granted_scopes: self.preflight_scopes.then(Default::default),
+            skip_keepalives: self.skip_keepalives,
+            max_line_length: self.max_line_length,
+            on_request: self.on_request,
+            retry_transient: self.retry_transient,
+        }
+    }
+}
+
+/// A Lichess API client.
+///
+/// Cloning is cheap: the key is shared and `reqwest::Client` is reference
+/// counted, so clones share one connection pool. Make one client and hand
+/// clones to every task rather than building a new one each time.
+#[derive(Clone)]
+pub struct Lichess {
+    key: Arc<str>,
+    /// The `Authorization` header, built once rather than on every request;
+    /// `None` without a key
+    auth: Option<reqwest::header::HeaderValue>,
+    base_url: String,
+    hclient: reqwest::Client,
+    max_retries: u32,
+    timeout: Option<Duration>,
+    max_body_size: Option<usize>,
+    validate_fen: bool,
+    /// Only set with `LichessBuilder::preflight_scopes`, filled in on first use
+    granted_scopes: Option<Arc<tokio::sync::OnceCell<Vec<String>>>>,
+    skip_keepalives: bool,
+    max_line_length: usize,
+    on_request: Option<RequestHook>,
+    retry_transient: bool,
+}
+
+// by hand, so that the key never ends up in logs
+impl fmt::Debug for Lichess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lichess")
+            .field("key", &"***")
+            .field("base_url", &self.base_url)
+            .field("timeout", &self.timeout)
+            .field("max_retries", &self.max_retries)
+            .field("max_body_size", &self.max_body_size)
+            .field("validate_fen", &self.validate_fen)
+            .field("preflight_scopes", &self.granted_scopes.is_some())
+            .field("skip_keepalives", &self.skip_keepalives)
+            .field("max_line_length", &self.max_line_length)
+            .field("on_request", &self.on_request.is_some())
+            .field("retry_transient", &self.retry_transient)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Lichess {
+    /// The most pieces a position can have for `tablebase` to know it
+    pub const TABLEBASE_PIECES: usize = 7;
+
+    /// Make a new client with a Lichess API key, or an empty one for anonymous access
+    pub fn new(key: impl Into<String>) -> Lichess {
+        Lichess::builder(key).build()
+    }
+
+    /// Build the url to send a user to so they can authorize your app, using PKCE.
+    ///
+    /// `code_challenge` is the base64url-encoded (without padding) SHA-256 of a
+    /// random `code_verifier` you keep for `exchange_code`. Lichess redirects
+    /// back to `redirect_uri` with a `code` and the given `state`. `base_url`
+    /// defaults to `https://lichess.org`, like `Lichess::builder`
+    pub fn oauth_url(
+        client_id: &str,
+        redirect_uri: &str,
+        scopes: &[&str],
+        code_challenge: &str,
+        state: Option<&str>,
+        base_url: Option<&str>,
+    ) -> String {
+        let scopes = scopes.join(" ");
+        let mut query = vec![
+            ("response_type", "code"),
+            ("client_id", client_id),
+            ("redirect_uri", redirect_uri),
+            ("code_challenge_method", "S256"),
+            ("code_challenge", code_challenge),
+            ("scope", scopes.as_str()),
+        ];
+
+        if let Some(state) = state {
+            query.push(("state", state));
+        }
+
+        format!(
+            "{}/oauth?{}",
+            base_url
+                .unwrap_or("https://lichess.org")
+                .trim_end_matches('/'),
+            Lichess::encode_form(&query)
+        )
+    }
+
+    /// Trade the `code` from an `oauth_url` redirect for a token; pass the same
+    /// `client_id`, `redirect_uri` and `base_url`, and the `code_verifier` behind
+    /// the challenge
+    pub async fn exchange_code(
+        client_id: &str,
+        redirect_uri: &str,
+        code: &str,
+        code_verifier: &str,
+        base_url: Option<&str>,
+    ) -> Response<OAuthToken> {
+        let body = Lichess::encode_form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("code_verifier", code_verifier),
+            ("redirect_uri", redirect_uri),
+            ("client_id", client_id),
+        ]);
+
+        // there's no token yet, so this goes out unauthenticated
+        let lichess = Lichess::with_base_url("", base_url.unwrap_or("https://lichess.org"));
+        let res = lichess.post(&lichess.api_url("token"), body).await?;
+
+        if !res["access_token"].is_string() {
+            return Err(LichessError::Unexpected {
+                endpoint: "token",
+                body: res,
+            });
+        }
+
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Start configuring a client, e.g. to go through a proxy
+    pub fn builder(key: impl Into<String>) -> LichessBuilder {
+        LichessBuilder {
+            key: key.into(),
+            base_url: String::from("https://lichess.org"),
+            client: None,
+            proxy: None,
+            timeout: None,
+            max_retries: 0,
+            max_body_size: None,
+            validate_fen: false,
+            preflight_scopes: false,
+            skip_keepalives: true,
+            max_line_length: 1024 * 1024,
+            on_request: None,
+            retry_transient: false,
+        }
+    }
+
+    /// Make a new client on top of an existing `reqwest::Client`, e.g. one with
+    /// a proxy, custom TLS or DNS already set up
+    pub fn with_client(key: impl Into<String>, client: reqwest::Client) -> Lichess {
+        Lichess::builder(key).client(client).build()
+    }
+
+    /// Make a new client with the API key in `LICHESS_TOKEN`
+    pub fn from_env() -> Result<Lichess, std::env::VarError> {
+        Lichess::from_env_var("LICHESS_TOKEN")
+    }
+
+    /// Make a new client with the API key in the environment variable `name`
+    pub fn from_env_var(name: &str) -> Result<Lichess, std::env::VarError> {
+        Ok(Lichess::new(std::env::var(name)?))
+    }
+
+    /// Make a new client pointed at a different Lichess instance,
+    /// e.g. `https://lichess.dev` or a local mock server
+    pub fn with_base_url(key: impl Into<String>, base_url: impl Into<String>) -> Lichess {
+        Lichess::builder(key).base_url(base_url).build()
+    }
+
+    /// Give up on regular requests that take longer than `timeout`.
+    ///
+    /// This only applies to requests that return a complete response;
+    /// streams (`stream`, `ndjson`, `events`, `board`) are meant to stay
+    /// open indefinitely and are never timed out.
+    pub fn with_timeout(mut self, timeout: Duration) -> Lichess {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Fail with `LichessError::BodyTooLarge` instead of buffering a response
+    /// bigger than `bytes`.
+    ///
+    /// Like `with_timeout`, this only applies to requests that return a complete
+    /// response (`get`, `post`, `export_game`, `broadcast_round`, ...). Streams
+    /// (`stream`, `ndjson`, `export_user_games`, `events`, ...) never buffer more
+    /// than a line, so use those for big downloads.
+    pub fn with_max_body_size(mut self, bytes: usize) -> Lichess {
+        self.max_body_size = Some(bytes);
+        self
+    }
+
+    /// Apply the configured timeout (if any) to a non-streaming request
+    fn timed(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self.timeout {
+            Some(timeout) => req.timeout(timeout),
+            None => req,
+        }
+    }
+
+    /// Attach the API key, unless there isn't one; an empty `Bearer` header gets
+    /// rejected even by endpoints that don't need a token
+    fn authed(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.auth {
+            Some(auth) => req.header(reqwest::header::AUTHORIZATION, auth.clone()),
+            None => req,
+        }
+    }
+
+    /// Retry requests that get rate limited (HTTP 429) up to `n` times,
+    /// waiting for `Retry-After` (or 60 seconds) between attempts; after that,
+    /// requests fail with `LichessError::RateLimited`
+    pub fn with_rate_limit_retries(mut self, n: u32) -> Lichess {
+        self.max_retries = n;
+        self
+    }
+
+    /// Send a request, sleeping and retrying on 429 if enabled
+    async fn send_with_retry(&self, req: reqwest::RequestBuilder) -> Response<reqwest::Response> {
+        let mut req = req.build()?;
+        let mut retries = 0;
+
+        loop {
+            let next = req.try_clone();
+            let res = self.execute(req).await?;
+
+            if res.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                return Ok(res);
+            }
+
+            let retry_after = res
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+
+            let next = match next {
+                Some(next) if retries < self.max_retries => next,
+                _ => return Err(LichessError::RateLimited { retry_after }),
+            };
+
+            tokio::time::sleep(retry_after.unwrap_or(Duration::from_secs(60))).await;
+
+            retries += 1;
+            req = next;
+        }
+    }
+
+    /// Build the full url of a Lichess api endpoint
+    fn api_url(&self, endpoint: &str) -> String {
+        format!("{}/api/{}", self.base_url, endpoint.trim_start_matches('/'))
+    }
+
+    /// Build the full url of a Lichess api endpoint with an encoded query string
+    fn api_url_query<K: AsRef<str>, V: AsRef<str>>(
+        &self,
+        endpoint: &str,
+        params: &[(K, V)],
+    ) -> String {
+        let url = self.api_url(endpoint);
+
+        if params.is_empty() {
+            url
+        } else {
+            format!("{url}?{}", Lichess::encode_form(params))
+        }
+    }
+
+    /// Build the full url of a Lichess page outside of `/api`
+    fn site_url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path.trim_start_matches('/'))
+    }
+
+    /// Send a request with any HTTP method to any url, returning the plaintext
+    /// response. `headers` are sent on top of the token, e.g. the `Content-Type`
+    /// of a `body` or the `Accept` format. Bad statuses become errors, and 429s
+    /// are retried; see `raw_request` to get the response untouched instead
+    pub async fn request_raw(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        body: Option<String>,
+        headers: &[(reqwest::header::HeaderName, &str)],
+    ) -> Response<String> {
+        let mut req = self.authed(self.hclient.request(method, url));
+
+        if let Some(body) = body {
+            req = req.body(body);
+        }
+
+        for (name, value) in headers {
+            req = req.header(name.clone(), *value);
+        }
+
+        let res = self.send_with_retry(self.timed(req)).await?;
+        self.handle_response(res).await
+    }
+
+    /// Send a request to a Lichess api endpoint the crate doesn't cover, and hand
+    /// back the response untouched, for its headers or to stream it yourself.
+    ///
+    /// The token and `with_timeout` are applied, but nothing else: unlike
+    /// `request_raw`, error statuses (429 included) are left for the caller to
+    /// handle. A `body` is sent as `application/x-www-form-urlencoded`
+    pub async fn raw_request(
+        &self,
+        method: reqwest::Method,
+        endpoint: &str,
+        body: Option<String>,
+    ) -> Response<reqwest::Response> {
+        let mut req = self.authed(self.hclient.request(method, self.api_url(endpoint)));
+
+        if let Some(body) = body {
+            req = req.body(body).header(
+                reqwest::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            );
+        }
+
+        self.execute(self.timed(req).build()?).await
+    }
+
+    /// Send a single request, reporting it to the `on_request` hook if there is one
+    async fn execute(&self, req: reqwest::Request) -> Response<reqwest::Response> {
+        let Some(hook) = &self.on_request else {
+            return self.send_request(req).await;
+        };
+
+        let endpoint = req.url().path().to_string();
+        let started = std::time::Instant::now();
+        let res = self.send_request(req).await;
+
+        hook(RequestMetric {
+            endpoint,
+            status: res.as_ref().ok().map(|res| res.status().as_u16()),
+            elapsed: started.elapsed(),
+        });
+
+        res
+    }
+
+    /// Send a single request
+    #[cfg(not(feature = "tracing"))]
+    async fn send_request(&self, req: reqwest::Request) -> Response<reqwest::Response> {
+        Ok(self.hclient.execute(req).await?)
+    }
+
+    /// Send a single request inside a span. Only the method and path are
+    /// recorded, never the headers, so the token stays out of the logs
+    #[cfg(feature = "tracing")]
+    async fn send_request(&self, req: reqwest::Request) -> Response<reqwest::Response> {
+        use tracing::Instrument;
+
+        let span = tracing::debug_span!(
+            "request",
+            method = %req.method(),
+            endpoint = req.url().path(),
+        );
+        let started = std::time::Instant::now();
+        let res = self.hclient.execute(req).instrument(span.clone()).await;
+
+        span.in_scope(|| match &res {
+            Ok(res) => tracing::debug!(
+                status = res.status().as_u16(),
+                elapsed = ?started.elapsed(),
+                "request finished"
+            ),
+            Err(e) => tracing::warn!(error = %e, elapsed = ?started.elapsed(), "request failed"),
+        });
+
+        Ok(res?)
+    }
+
+    /// Read a response's body, turning bad status codes into an `ApiError`
+    async fn handle_response(&self, res: reqwest::Response) -> Response<String> {
+        let status = res.status().as_u16();
+        let msg = match self.max_body_size {
+            Some(limit) => Lichess::read_limited(res, limit).await?,
+            None => res.text().await?,
+        };
+
+        match status {
+            200..=299 => Ok(msg),
+            _ => Err(Lichess::api_error(status, msg)),
+        }
+    }
+
+    /// Turn a bad status code and its body into an `ApiError`
+    fn api_error(status: u16, msg: String) -> LichessError {
+        if msg.is_empty() {
+            return ApiError::new(status, Value::Null).into();
+        }
+
+        // not every error page is json (e.g. a proxy's 502)
+        let body = serde_json::from_str(msg.as_str()).unwrap_or(Value::String(msg));
+        ApiError::new(status, body).into()
+    }
+
+    /// Read a response's body, giving up as soon as it's bigger than `limit`
+    async fn read_limited(mut res: reqwest::Response, limit: usize) -> Response<String> {
+        if res.content_length().is_some_and(|len| len > limit as u64) {
+            return Err(LichessError::BodyTooLarge { limit });
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = res.chunk().await? {
+            if body.len() + chunk.len() > limit {
+                return Err(LichessError::BodyTooLarge { limit });
+            }
+
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// Get a plaintext response from a server
+    pub async fn get_raw(&self, url: &str) -> Response<String> {
+        self.get_retrying(url, &[]).await
+    }
+
+    /// Get a plaintext response from a server, asking for the `accept` format,
+    /// e.g. `application/x-chess-pgn` or `application/json` from export endpoints
+    pub async fn get_raw_accept(&self, url: &str, accept: &str) -> Response<String> {
+        self.get_retrying(url, &[(reqwest::header::ACCEPT, accept)])
+            .await
+    }
+
+    /// Send a GET, retrying it once if it couldn't connect or timed out and
+    /// `LichessBuilder::retry_transient` is on
+    async fn get_retrying(
+        &self,
+        url: &str,
+        headers: &[(reqwest::header::HeaderName, &str)],
+    ) -> Response<String> {
+        match self
+            .request_raw(reqwest::Method::GET, url, None, headers)
+            .await
+        {
+            Err(LichessError::Transport(e))
+                if self.retry_transient && (e.is_connect() || e.is_timeout()) =>
+            {
+                log::warn!("retrying after a transient error: {e}");
+                self.request_raw(reqwest::Method::GET, url, None, headers)
+                    .await
+            }
+            res => res,
+        }
+    }
+
+    /// Get and parse a JSON response from a server
+    pub async fn get(&self, url: &str) -> Response<Value> {
+        Lichess::parse_json(self.get_raw(url).await?.as_str())
+    }
+
+    /// Post a form-encoded body to a server
+    pub async fn post_raw(&self, url: &str, body: String) -> Response<String> {
+        self.post_with_content_type(url, body, "application/x-www-form-urlencoded")
+            .await
+    }
+
+    /// Post to a server with a body that isn't form-encoded, e.g. `text/plain`
+    /// or `application/json`
+    pub async fn post_with_content_type(
+        &self,
+        url: &str,
+        body: String,
+        content_type: &str,
+    ) -> Response<String> {
+        self.request_raw(
+            reqwest::Method::POST,
+            url,
+            Some(body),
+            &[(reqwest::header::CONTENT_TYPE, content_type)],
+        )
+        .await
+    }
+
+    /// Post to a server, returning json
+    pub async fn post(&self, url: &str, body: String) -> Response<Value> {
+        Lichess::parse_json(self.post_raw(url, body).await?.as_str())
+    }
+
+    /// Send a delete request to a server
+    pub async fn delete_raw(&self, url: &str) -> Response<String> {
+        self.request_raw(reqwest::Method::DELETE, url, None, &[])
+            .await
+    }
+
+    /// Send a delete request to a server, returning json
+    pub async fn delete(&self, url: &str) -> Response<Value> {
+        Lichess::parse_json(self.delete_raw(url).await?.as_str())
+    }
+
+    /// Parse a JSON body, taking an empty one (which some endpoints answer
+    /// with) as `null`
+    fn parse_json(body: &str) -> Response<Value> {
+        if body.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+
+        Ok(serde_json::from_str(body)?)
+    }
+
+    /// Get a Lichess api endpoint
+    pub async fn get_api(&self, endpoint: &str) -> Response<Value> {
+        self.get(&self.api_url(endpoint)).await
+    }
+
+    /// Get a Lichess api endpoint, url-encoding `params` into the query string
+    pub async fn get_api_query(&self, endpoint: &str, params: &[(&str, &str)]) -> Response<Value> {
+        self.get(&self.api_url_query(endpoint, params)).await
+    }
+
+    /// Post to a Lichess api endpoint, returning json
+    pub async fn post_api(&self, endpoint: &str, body: String) -> Response<Value> {
+        self.post(&self.api_url(endpoint), body).await
+    }
+
+    /// Post to a Lichess api endpoint
+    pub async fn post_api_raw(&self, endpoint: &str, body: String) -> Response<String> {
+        self.post_raw(&self.api_url(endpoint), body).await
+    }
+
+    /// Get the email of your account
+    /// Requires `email:read` scope
+    pub async fn email(&self) -> Response<String> {
+        self.require_scope("email:read").await?;
+
+        let res = self.get_api("account/email").await?;
+
+        if let Value::Object(err) = &res["error"] {
+            return Err(LichessError::Lichess(Value::Object(err.clone())));
+        }
+
+        if let Value::String(email) = &res["email"] {
+            return Ok(email.clone());
+        }
+
+        Err(LichessError::Unexpected {
+            endpoint: "account/email",
+            body: res,
+        })
+    }
+
+    /// Get your account details
+    /// Requires no scopes
+    pub async fn account(&self) -> Response<Value> {
+        self.get_api("account").await
+    }
+
+    /// Get your account details, parsed into an `Account`
+    /// Requires no scopes
+    pub async fn account_typed(&self) -> Response<Account> {
+        Ok(serde_json::from_value(self.account().await?)?)
+    }
+
+    /// Check the client's token, e.g. to make sure it has the scopes you need at
+    /// startup rather than failing on the first request that needs them
+    /// Requires no scopes
+    pub async fn token_info(&self) -> Response<TokenInfo> {
+        let res = self
+            .post_with_content_type(
+                &self.api_url("token/test"),
+                self.key.to_string(),
+                "text/plain",
+            )
+            .await?;
+        let mut res = Lichess::parse_json(&res)?;
+
+        // the response maps each token to its info, or null if it's not valid
+        match res.get_mut(&*self.key).map(Value::take) {
+            Some(Value::Null) => Err(LichessError::InvalidToken),
+            Some(info) => Ok(serde_json::from_value(info)?),
+            None => Err(LichessError::Unexpected {
+                endpoint: "token/test",
+                body: res,
+            }),
+        }
+    }
+
+    /// Revoke the client's token; every request after this will fail
+    /// Requires no scopes
+    pub async fn revoke_token(&self) -> Response<()> {
+        // success is a 204 with no body; anything else is already an error
+        self.delete_raw(&self.api_url("token")).await?;
+        Ok(())
+    }
+
+    /// Get your account preferences, e.g. premoves and takebacks
+    /// Requires `preference:read` scope
+    pub async fn preferences(&self) -> Response<Value> {
+        self.require_scope("preference:read").await?;
+
+        self.get_api("account/preferences").await
+    }
+
+    /// Get whether kid mode is on for your account
+    /// Requires `preference:read` scope
+    pub async fn kid_mode(&self) -> Response<bool> {
+        self.require_scope("preference:read").await?;
+
+        let res = self.get_api("account/kid").await?;
+
+        match &res["kid"] {
+            Value::Bool(kid) => Ok(*kid),
+            _ => Err(LichessError::Unexpected {
+                endpoint: "account/kid",
+                body: res,
+            }),
+        }
+    }
+
+    /// Turn kid mode on or off for your account
+    /// Requires `preference:write` scope
+    pub async fn set_kid_mode(&self, kid: bool) -> Response<bool> {
+        self.require_scope("preference:write").await?;
+
+        let endpoint = format!(
+            "account/kid?{}",
+            Lichess::encode_form(&[("v", kid.to_string())])
+        );
+        let res = self.post_api(&endpoint, String::new()).await?;
+
+        Lichess::parse_ok("account/kid", res)
+    }
+
+    /// Get a player's public profile
+    /// Requires no scopes
+    pub async fn user(&self, username: &str) -> Response<User> {
+        let path = Lichess::encode_path(username);
+        match self.get_api(&format!("user/{path}")).await {
+            Ok(res) => Ok(serde_json::from_value(res)?),
+            Err(e) if e.status() == Some(404) => Err(LichessError::UserNotFound {
+                username: username.to_string(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get a player's rating history in every perf
+    /// Requires no scopes
+    pub async fn rating_history(&self, username: &str) -> Response<Vec<RatingHistory>> {
+        let username = Lichess::encode_path(username);
+        let res = self
+            .get_api(&format!("user/{username}/rating-history"))
+            .await?;
+
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Get a player's recent activity, one entry per day with the games,
+    /// puzzles and tournaments they played
+    /// Requires no scopes
+    pub async fn activity(&self, username: &str) -> Response<Value> {
+        let username = Lichess::encode_path(username);
+        self.get_api(&format!("user/{username}/activity")).await
+    }
+
+    /// Get the head-to-head record of two players; with `matchup`, also the
+    /// record of the match they're currently playing, if any
+    /// Requires no scopes
+    pub async fn crosstable(
+        &self,
+        user1: &str,
+        user2: &str,
+        matchup: bool,
+    ) -> Response<Crosstable> {
+        let matchup = matchup.to_string();
+        let user1 = Lichess::encode_path(user1);
+        let user2 = Lichess::encode_path(user2);
+        let res = self
+            .get_api_query(
+                &format!("crosstable/{user1}/{user2}"),
+                &[("matchup", matchup.as_str())],
+            )
+            .await?;
+
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Follow a player
+    /// Requires `follow:write` scope
+    pub async fn follow(&self, username: &str) -> Response<bool> {
+        self.require_scope("follow:write").await?;
+
+        let path = Lichess::encode_path(username);
+        match self
+            .post_api(&format!("rel/follow/{path}"), String::new())
+            .await
+        {
+            Ok(res) => Lichess::parse_ok("rel/follow/{username}", res),
+            Err(e) if e.status() == Some(404) => Err(LichessError::UserNotFound {
+                username: username.to_string(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stop following a player
+    /// Requires `follow:write` scope
+    pub async fn unfollow(&self, username: &str) -> Response<bool> {
+        self.require_scope("follow:write").await?;
+
+        let path = Lichess::encode_path(username);
+        match self
+            .post_api(&format!("rel/unfollow/{path}"), String::new())
+            .await
+        {
+            Ok(res) => Lichess::parse_ok("rel/unfollow/{username}", res),
+            Err(e) if e.status() == Some(404) => Err(LichessError::UserNotFound {
+                username: username.to_string(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Send a player a private message.
+    ///
+    /// Messaging is heavily throttled: past the limit this fails with
+    /// `LichessError::RateLimited`, after any `with_rate_limit_retries`
+    /// Requires `msg:write` scope
+    pub async fn send_message(&self, username: &str, text: &str) -> Response<bool> {
+        self.require_scope("msg:write").await?;
+
+        let path = Lichess::encode_path(username);
+        // the inbox lives outside of the api
+        match self
+            .post(
+                &self.site_url(&format!("inbox/{path}")),
+                Lichess::encode_form(&[("text", text)]),
+            )
+            .await
+        {
+            Ok(res) => Lichess::parse_ok("inbox/{username}", res),
+            Err(e) if e.status() == Some(404) => Err(LichessError::UserNotFound {
+                username: username.to_string(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the online/playing status of up to 100 players at once
+    /// Requires no scopes
+    pub async fn users_status(&self, ids: &[&str]) -> Response<Vec<UserStatus>> {
+        if ids.len() > 100 {
+            return Err(LichessError::InvalidInput(format!(
+                "can't get the status of more than 100 users, got {}",
+                ids.len()
+            )));
+        }
+
+        let ids = ids.join(",");
+        let res = self
+            .get_api_query("users/status", &[("ids", ids.as_str())])
+            .await?;
+
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Get the top `count` (1 to 200) players of a perf, e.g. `"blitz"`
+    /// Requires no scopes
+    pub async fn leaderboard(&self, perf: &str, count: u8) -> Response<Vec<LeaderboardEntry>> {
+        if !(1..=200).contains(&count) {
+            return Err(LichessError::InvalidInput(format!(
+                "leaderboards hold 1 to 200 players, got {count}"
+            )));
+        }
+
+        let perf = Lichess::encode_path(perf);
+        let mut res = self.get_api(&format!("player/top/{count}/{perf}")).await?;
+
+        let users: Vec<LeaderboardUser> = match res.get_mut("users").map(Value::take) {
+            Some(users) => serde_json::from_value(users)?,
+            None => {
+                return Err(LichessError::Unexpected {
+                    endpoint: "player/top",
+                    body: res,
+                })
+            }
+        };
+
+        Ok(users
+            .into_iter()
+            .filter_map(|user| {
+                let rating = user.perfs.get(perf)?.rating;
+                Some(LeaderboardEntry {
+                    id: user.id,
+                    username: user.username,
+                    title: user.title,
+                    rating,
+                })
+            })
+            .collect())
+    }
+
+    /// Get the games you're currently playing, up to `max` of them
+    /// Requires no scopes
+    pub async fn ongoing_games(&self, max: Option<u32>) -> Response<Vec<OngoingGame>> {
+        let nb = max.map(|nb| nb.to_string());
+        let query: Vec<_> = nb.iter().map(|nb| ("nb", nb.as_str())).collect();
+
+        let mut res = self.get_api_query("account/playing", &query).await?;
+
+        let games = res.get_mut("nowPlaying").map(Value::take);
+        match games {
+            Some(games) => Ok(serde_json::from_value(games)?),
+            None => Err(LichessError::Unexpected {
+                endpoint: "account/playing",
+                body: res,
+            }),
+        }
+    }
+
+    /// Challenge the AI, returning the game id; no `clock` means an unlimited game,
+    /// no `color` a random one
+    /// Requires `challenge:write` scope
+    pub async fn ai(
+        &self,
+        level: i32,
+        color: Option<Color>,
+        clock: Option<ClockSettings>,
+        initial: Option<String>,
+    ) -> Response<String> {
+        Ok(self.ai_typed(level, color, clock, initial).await?.id)
+    }
+
+    /// Challenge the AI, returning the created game
+    /// Requires `challenge:write` scope
+    pub async fn ai_typed(
+        &self,
+        level: i32,
+        color: Option<Color>,
+        clock: Option<ClockSettings>,
+        initial: Option<String>,
+    ) -> Response<Challenge> {
+        self.require_scope("challenge:write").await?;
+
+        let mut body = vec![("level", level.to_string())];
+
+        body.push(match color {
+            Some(Color::White) => ("color", String::from("white")),
+            Some(Color::Black) => ("color", String::from("black")),
+            None => ("color", String::from("random")),
+        });
+
+        body.extend(Lichess::encode_clock(
+            clock.as_ref(),
+            ClockStyle::Challenge,
+        )?);
+
+        self.check_fen(initial.as_deref())?;
+        if let Some(fen) = initial {
+            body.push(("fen", fen));
+        }
+
+        let res = self
+            .post_api("challenge/ai", Lichess::encode_form(&body))
+            .await?;
+
+        if let Value::Object(err) = &res["error"] {
+            return Err(LichessError::Lichess(Value::Object(err.clone())));
+        }
+
+        if !res["id"].is_string() {
+            return Err(LichessError::Unexpected {
+                endpoint: "challenge/ai",
+                body: res,
+            });
+        }
+
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Challenge another player, returning the challenge id; no `color` means
+    /// a random one
+    /// Requires `challenge:write` scope
+    pub async fn challenge_user(
+        &self,
+        username: &str,
+        rated: bool,
+        color: Option<Color>,
+        clock: Option<ClockSettings>,
+        variant: Option<String>,
+        fen: Option<String>,
+    ) -> Response<String> {
+        let mut req = ChallengeRequest::new().rated(rated);
+
+        if let Some(color) = color {
+            req = req.color(color);
+        }
+
+        if let Some(clock) = clock {
+            req = req.clock(clock);
+        }
+
+        if let Some(variant) = variant {
+            req = req.variant(variant);
+        }
+
+        if let Some(fen) = fen {
+            req = req.fen(fen);
+        }
+
+        self.challenge(username, &req).await
+    }
+
+    /// Challenge another player, returning the challenge id
+    /// Requires `challenge:write` scope
+    pub async fn challenge(&self, username: &str, req: &ChallengeRequest) -> Response<String> {
+        self.require_scope("challenge:write").await?;
+
+        self.check_fen(req.fen.as_deref())?;
+
+        let username = Lichess::encode_path(username);
+        let res = self
+            .post_api_raw(
+                &format!("challenge/{username}"),
+                Lichess::encode_form(&req.body()?),
+            )
+            .await?;
+
+        // with `keepAliveStream` the body is ndjson; the challenge is the first line
+        let res: Value = match serde_json::Deserializer::from_str(res.as_str())
+            .into_iter::<Value>()
+            .next()
+        {
+            Some(res) => res?,
+            None => Value::Null,
+        };
+
+        if let Value::Object(err) = &res["error"] {
+            return Err(LichessError::Lichess(Value::Object(err.clone())));
+        }
+
+        // older responses nest the challenge, newer ones don't
+        if let Value::String(id) = &res["challenge"]["id"] {
+            return Ok(id.clone());
+        }
+
+        if let Value::String(id) = &res["id"] {
+            return Ok(id.clone());
+        }
+
+        Err(LichessError::Unexpected {
+            endpoint: "challenge/{username}",
+            body: res,
+        })
+    }
+
+    /// Create a challenge anyone can join through its links, optionally `name`d;
+    /// no `clock` means an unlimited game
+    /// Requires `challenge:write` scope
+    pub async fn open_challenge(
+        &self,
+        clock: Option<ClockSettings>,
+        rated: bool,
+        name: Option<&str>,
+    ) -> Response<OpenChallenge> {
+        self.require_scope("challenge:write").await?;
+
+        let mut body = vec![("rated", rated.to_string())];
+
+        body.extend(Lichess::encode_clock(
+            clock.as_ref(),
+            ClockStyle::Challenge,
+        )?);
+
+        if let Some(name) = name {
+            body.push(("name", name.to_string()));
+        }
+
+        let res = self
+            .post_api("challenge/open", Lichess::encode_form(&body))
+            .await?;
+
+        if let Value::Object(err) = &res["error"] {
+            return Err(LichessError::Lichess(Value::Object(err.clone())));
+        }
+
+        if !res["urlWhite"].is_string() {
+            return Err(LichessError::Unexpected {
+                endpoint: "challenge/open",
+                body: res,
+            });
+        }
+
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Pair players against each other, each pair being the OAuth tokens of
+    /// white and black (with `challenge:write` scope). `start_at` is when to
+    /// create the games, in milliseconds since the epoch; `None` means now
+    /// Requires `challenge:bulk` scope
+    pub async fn bulk_pairing(
+        &self,
+        players: &[(String, String)],
+        clock: ClockSettings,
+        rated: bool,
+        start_at: Option<i64>,
+    ) -> Response<Value> {
+        self.require_scope("challenge:bulk").await?;
+
+        if players.is_empty() {
+            return Err(LichessError::InvalidInput(String::from(
+                "a bulk pairing needs at least one pair of players",
+            )));
+        }
+
+        let bad_token = |token: &str| token.is_empty() || token.contains([',', ':']);
+        if players.iter().any(|(w, b)| bad_token(w) || bad_token(b)) {
+            return Err(LichessError::InvalidInput(String::from(
+                "player tokens must be non-empty and can't contain `,` or `:`",
+            )));
+        }
+
+        let players = players
+            .iter()
+            .map(|(white, black)| format!("{white}:{black}"))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let mut body = vec![("players", players), ("rated", rated.to_string())];
+
+        body.extend(Lichess::encode_clock(Some(&clock), ClockStyle::Challenge)?);
+
+        if let Some(start_at) = start_at {
+            body.push(("pairAt", start_at.to_string()));
+        }
+
+        let res = self
+            .post_api("bulk-pairing", Lichess::encode_form(&body))
+            .await?;
+
+        if let Value::Object(err) = &res["error"] {
+            return Err(LichessError::Lichess(Value::Object(err.clone())));
+        }
+
+        Ok(res)
+    }
+
+    /// Get the bulk pairings you've scheduled
+    /// Requires `challenge:bulk` scope
+    pub async fn list_bulk_pairings(&self) -> Response<Vec<BulkPairing>> {
+        self.require_scope("challenge:bulk").await?;
+
+        let mut res = self.get_api("bulk-pairing").await?;
+
+        let bulks = res.get_mut("bulks").map(Value::take);
+        match bulks {
+            Some(bulks) => Ok(serde_json::from_value(bulks)?),
+            None => Err(LichessError::Unexpected {
+                endpoint: "bulk-pairing",
+                body: res,
+            }),
+        }
+    }
+
+    /// Cancel a bulk pairing, along with any games it already created that
+    /// haven't started
+    /// Requires `challenge:bulk` scope
+    pub async fn cancel_bulk_pairing(&self, id: &str) -> Response<bool> {
+        self.require_scope("challenge:bulk").await?;
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .delete(&self.api_url(&format!("bulk-pairing/{id}")))
+            .await?;
+
+        Lichess::parse_ok("bulk-pairing/{id}", res)
+    }
+
+    /// Fail early if `LichessBuilder::preflight_scopes` is on and the token
+    /// lacks `scope`
+    async fn require_scope(&self, scope: &'static str) -> Response<()> {
+        let granted = match &self.granted_scopes {
+            Some(granted) => granted,
+            None => return Ok(()),
+        };
+
+        let granted = granted
+            .get_or_try_init(|| async { Ok::<_, LichessError>(self.token_info().await?.scopes) })
+            .await?;
+
+        if granted.iter().any(|s| s == scope) {
+            Ok(())
+        } else {
+            Err(LichessError::MissingScope(scope))
+        }
+    }
+
+    /// Reject a malformed FEN early, if `LichessBuilder::validate_fen` is on
+    fn check_fen(&self, fen: Option<&str>) -> Response<()> {
+        match fen {
+            Some(fen) if self.validate_fen => Lichess::validate_fen(fen)
+                .map_err(|e| LichessError::InvalidInput(format!("invalid FEN `{fen}`: {e}"))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that a standard FEN is well-formed; this doesn't check that the
+    /// position is legal, and rejects variant extensions like crazyhouse pockets
+    fn validate_fen(fen: &str) -> Result<(), &'static str> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if !(4..=6).contains(&fields.len()) {
+            return Err("expected 4 to 6 fields");
+        }
+
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err("expected 8 ranks");
+        }
+
+        for rank in ranks {
+            let mut squares = 0;
+            for c in rank.chars() {
+                match c {
+                    '1'..='8' => squares += c as u32 - '0' as u32,
+                    c if "pnbrqkPNBRQK".contains(c) => squares += 1,
+                    _ => return Err("unknown piece"),
+                }
+            }
+
+            if squares != 8 {
+                return Err("expected 8 squares in every rank");
+            }
+        }
+
+        if fields[0].matches('K').count() != 1 || fields[0].matches('k').count() != 1 {
+            return Err("expected one king per side");
+        }
+
+        if fields[1] != "w" && fields[1] != "b" {
+            return Err("side to move must be `w` or `b`");
+        }
+
+        // files are allowed as well as `KQkq`, for chess960
+        if fields[2] != "-"
+            && !fields[2]
+                .chars()
+                .all(|c| "KQkqABCDEFGHabcdefgh".contains(c))
+        {
+            return Err("invalid castling rights");
+        }
+
+        let ep = fields[3].as_bytes();
+        if fields[3] != "-"
+            && !(ep.len() == 2
+                && (b'a'..=b'h').contains(&ep[0])
+                && (ep[1] == b'3' || ep[1] == b'6'))
+        {
+            return Err("invalid en passant square");
+        }
+
+        if fields[4..].iter().any(|n| n.parse::<u32>().is_err()) {
+            return Err("move counters must be numbers");
+        }
+
+        Ok(())
+    }
+
+    /// Encode clock settings the way a family of endpoints expects them;
+    /// no clock at all means an unlimited game
+    fn encode_clock(
+        clock: Option<&ClockSettings>,
+        style: ClockStyle,
+    ) -> Response<Vec<(&'static str, String)>> {
+        let Some(clock) = clock else {
+            return Ok(Vec::new());
+        };
+
+        if clock.is_correspondence {
+            return Ok(vec![("days", clock.days.to_string())]);
+        }
+
+        match style {
+            ClockStyle::Challenge => Ok(vec![
+                ("clock.limit", clock.limit.to_string()),
+                ("clock.increment", clock.increment.to_string()),
+            ]),
+
+            // seeks take their initial time in minutes, not seconds
+            ClockStyle::Seek => match Lichess::seek_minutes(clock.limit) {
+                Some(time) => Ok(vec![
+                    ("time", time),
+                    ("increment", clock.increment.to_string()),
+                ]),
+                None => Err(LichessError::InvalidInput(format!(
+                    "seeks can't start with {}s on the clock; use 0, 15, 30, 45 or 90 \
+                     seconds, or whole minutes up to 180",
+                    clock.limit
+                ))),
+            },
+        }
+    }
+
+    /// The minute values the seek endpoint takes, for a limit in seconds
+    fn seek_minutes(limit: u32) -> Option<String> {
+        match limit {
+            15 => Some(String::from("0.25")),
+            30 => Some(String::from("0.5")),
+            45 => Some(String::from("0.75")),
+            90 => Some(String::from("1.5")),
+            limit if limit.is_multiple_of(60) && limit <= 180 * 60 => {
+                Some((limit / 60).to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Create a seek; no `clock` means an unlimited game. Lichess only takes
+    /// some starting times for seeks: 0, 15, 30, 45 or 90 seconds, or whole
+    /// minutes up to 180. Any other `limit` fails with `LichessError::InvalidInput`
+    /// Requires `board:play` scope
+    pub async fn seek(
+        &self,
+        rated: bool,
+        color: Option<Color>,
+        clock: Option<ClockSettings>,
+        initial: Option<String>,
+    ) -> Response<Option<String>> {
+        self.require_scope("board:play").await?;
+
+        let mut body = match color {
+            Some(Color::White) => vec![("color", String::from("white"))],
+            Some(Color::Black) => vec![("color", String::from("black"))],
+            None => vec![("color", String::from("random"))],
+        };
+
+        if rated {
+            body.push(("rated", String::from("true")));
+        }
+
+        body.extend(Lichess::encode_clock(clock.as_ref(), ClockStyle::Seek)?);
+
+        self.check_fen(initial.as_deref())?;
+        if let Some(fen) = initial {
+            body.push(("fen", fen));
+        }
+
+        let res = self
+            .post_api_raw("board/seek", Lichess::encode_form(&body))
+            .await?;
+
+        if res.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(res))
+        }
+    }
+
+    /// Accept an incoming challenge
+    /// Requires `challenge:write` scope
+    pub async fn accept_challenge(&self, id: &str) -> Response<bool> {
+        self.require_scope("challenge:write").await?;
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("challenge/{id}/accept"), String::new())
+            .await?;
+
+        Lichess::parse_ok("challenge/{id}/accept", res)
+    }
+
+    /// Decline an incoming challenge, optionally giving a reason
+    /// Requires `challenge:write` scope
+    pub async fn decline_challenge(
+        &self,
+        id: &str,
+        reason: Option<ChallengeDeclineReason>,
+    ) -> Response<bool> {
+        self.require_scope("challenge:write").await?;
+
+        let body = match reason {
+            Some(reason) => Lichess::encode_form(&[("reason", reason.as_str())]),
+            None => String::new(),
+        };
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("challenge/{id}/decline"), body)
+            .await?;
+
+        Lichess::parse_ok("challenge/{id}/decline", res)
+    }
+
+    /// Cancel a challenge you created; `opponent_token` is only
+    /// needed for challenges made in bulk
+    /// Requires `challenge:write` scope
+    pub async fn cancel_challenge(&self, id: &str, opponent_token: Option<&str>) -> Response<bool> {
+        self.require_scope("challenge:write").await?;
+
+        let id = Lichess::encode_path(id);
+        let endpoint = match opponent_token {
+            Some(token) => format!(
+                "challenge/{id}/cancel?{}",
+                Lichess::encode_form(&[("opponentToken", token)])
+            ),
+            None => format!("challenge/{id}/cancel"),
+        };
+
+        let res = self.post_api(&endpoint, String::new()).await?;
+
+        Lichess::parse_ok("challenge/{id}/cancel", res)
+    }
+
+    /// Make a move in a game, given in UCI. A refused move fails with
+    /// `LichessError::Move`, saying why
+    /// Requires `board:play` scope
+    pub async fn make_move(&self, id: &str, m: &str, draw: bool) -> Response<bool> {
+        self.play_move(PlayAs::Board, id, m, draw).await
+    }
+
+    /// Resign a game
+    /// Requires `board:play` scope
+    pub async fn resign(&self, id: &str) -> Response<bool> {
+        self.play_resign(PlayAs::Board, id).await
+    }
+
+    /// Abort a game, only possible before the first move
+    /// Requires `board:play` scope
+    pub async fn abort(&self, id: &str) -> Response<bool> {
+        self.play_abort(PlayAs::Board, id).await
+    }
+
+    /// Claim victory once the opponent has been gone long enough, see
+    /// `BoardEvent::OpponentGone`; claiming too early fails with an `ApiError`
+    /// Requires `board:play` scope
+    pub async fn claim_victory(&self, id: &str) -> Response<bool> {
+        self.require_scope("board:play").await?;
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("board/game/{id}/claim-victory"), String::new())
+            .await?;
+
+        Lichess::parse_ok("board/game/{id}/claim-victory", res)
+    }
+
+    /// Berserk in an arena game, halving your clock for an extra point if you win;
+    /// only possible before your first move, and fails with an `ApiError` otherwise
+    /// Requires `board:play` scope
+    pub async fn berserk(&self, game_id: &str) -> Response<bool> {
+        self.require_scope("board:play").await?;
+
+        let game_id = Lichess::encode_path(game_id);
+        let res = self
+            .post_api(&format!("board/game/{game_id}/berserk"), String::new())
+            .await?;
+
+        Lichess::parse_ok("board/game/{id}/berserk", res)
+    }
+
+    /// Give your opponent `seconds` (up to a day) more on their clock; not every
+    /// game allows this
+    /// Requires `challenge:write` scope
+    pub async fn add_time(&self, game_id: &str, seconds: u32) -> Response<bool> {
+        self.require_scope("challenge:write").await?;
+
+        if !(1..=86400).contains(&seconds) {
+            return Err(LichessError::InvalidInput(format!(
+                "can only add 1 to 86400 seconds, got {seconds}"
+            )));
+        }
+
+        let game_id = Lichess::encode_path(game_id);
+        match self
+            .post_api(
+                &format!("round/{game_id}/add-time/{seconds}"),
+                String::new(),
+            )
+            .await
+        {
+            Ok(res) => Lichess::parse_ok("round/{id}/add-time/{seconds}", res),
+            Err(e) if e.status() == Some(400) => Err(LichessError::InvalidInput(format!(
+                "can't add time to game {game_id}, it may not allow giving time ({e})"
+            ))),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Propose a takeback, or accept the opponent's
+    /// Requires `board:play` scope
+    pub async fn offer_takeback(&self, id: &str) -> Response<bool> {
+        self.require_scope("board:play").await?;
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("board/game/{id}/takeback/yes"), String::new())
+            .await?;
+
+        Lichess::parse_ok("board/game/{id}/takeback/yes", res)
+    }
+
+    /// Decline the opponent's takeback proposal
+    /// Requires `board:play` scope
+    pub async fn decline_takeback(&self, id: &str) -> Response<bool> {
+        self.require_scope("board:play").await?;
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("board/game/{id}/takeback/no"), String::new())
+            .await?;
+
+        Lichess::parse_ok("board/game/{id}/takeback/no", res)
+    }
+
+    /// Offer a draw, or accept the opponent's
+    /// Requires `board:play` scope
+    pub async fn offer_draw(&self, id: &str) -> Response<bool> {
+        self.require_scope("board:play").await?;
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("board/game/{id}/draw/yes"), String::new())
+            .await?;
+
+        Lichess::parse_ok("board/game/{id}/draw/yes", res)
+    }
+
+    /// Decline the opponent's draw offer
+    /// Requires `board:play` scope
+    pub async fn decline_draw(&self, id: &str) -> Response<bool> {
+        self.require_scope("board:play").await?;
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("board/game/{id}/draw/no"), String::new())
+            .await?;
+
+        Lichess::parse_ok("board/game/{id}/draw/no", res)
+    }
+
+    /// Post a message to a game's chat
+    /// Requires `board:play` scope
+    pub async fn write_chat(&self, id: &str, room: ChatRoom, text: &str) -> Response<bool> {
+        self.play_chat(PlayAs::Board, id, room, text).await
+    }
+
+    /// Get the chat history of a game
+    /// Requires `board:play` scope
+    pub async fn fetch_chat(&self, id: &str) -> Response<Vec<ChatMessage>> {
+        self.require_scope("board:play").await?;
+
+        let id = Lichess::encode_path(id);
+        match self.get_api(&format!("board/game/{id}/chat")).await? {
+            Value::Null => Ok(Vec::new()),
+            res => Ok(serde_json::from_value(res)?),
+        }
+    }
+
+    /// Upgrade your account to a BOT account.
+    ///
+    /// This is irreversible, and only works on accounts that haven't
+    /// played any games yet; otherwise Lichess answers with a 400 error
+    /// Requires `bot:play` scope
+    pub async fn upgrade_to_bot(&self) -> Response<bool> {
+        self.require_scope("bot:play").await?;
+
+        let res = self.post_api("bot/account/upgrade", String::new()).await?;
+
+        Lichess::parse_ok("bot/account/upgrade", res)
+    }
+
+    /// Make a move in a game as a bot
+    /// Requires `bot:play` scope
+    pub async fn bot_move(&self, id: &str, m: &str, draw: bool) -> Response<bool> {
+        self.play_move(PlayAs::Bot, id, m, draw).await
+    }
+
+    /// Resign a game as a bot
+    /// Requires `bot:play` scope
+    pub async fn bot_resign(&self, id: &str) -> Response<bool> {
+        self.play_resign(PlayAs::Bot, id).await
+    }
+
+    /// Abort a game as a bot, only possible before the first move
+    /// Requires `bot:play` scope
+    pub async fn bot_abort(&self, id: &str) -> Response<bool> {
+        self.play_abort(PlayAs::Bot, id).await
+    }
+
+    /// Post a message to a game's chat as a bot
+    /// Requires `bot:play` scope
+    pub async fn bot_chat(&self, id: &str, room: ChatRoom, text: &str) -> Response<bool> {
+        self.play_chat(PlayAs::Bot, id, room, text).await
+    }
+
+    /// Get a listener to a bot game's event stream, parsed into `BoardEvent`s
+    /// Requires `bot:play` scope
+    pub async fn bot_game_stream(&self, id: &str) -> Response<impl Stream<Item = BoardEvent>> {
+        self.require_scope("bot:play").await?;
+
+        let id = Lichess::encode_path(id);
+        self.ndjson(&self.api_url(&format!("{}/game/stream/{id}", PlayAs::Bot.prefix())))
+            .await
+    }
+
+    async fn play_move(&self, play_as: PlayAs, id: &str, m: &str, draw: bool) -> Response<bool> {
+        self.require_scope(play_as.scope()).await?;
+
+        let prefix = play_as.prefix();
+        let query = Lichess::encode_form(&[("offeringDraw", draw.to_string())]);
+        let id = Lichess::encode_path(id);
+        let m = Lichess::encode_path(m);
+        let res = match self
+            .post_api(
+                &format!("{prefix}/game/{id}/move/{m}?{query}"),
+                String::new(),
+            )
+            .await
+        {
+            Ok(res) => res,
+            Err(LichessError::Http(e)) if e.code() == 400 => {
+                return Err(LichessError::Move(MoveError::from_body(e.message())))
+            }
+            Err(e) => return Err(e),
+        };
+
+        let endpoint = match play_as {
+            PlayAs::Board => "board/game/{id}/move/{move}",
+            PlayAs::Bot => "bot/game/{id}/move/{move}",
+        };
+
+        match Lichess::parse_ok(endpoint, res) {
+            Err(LichessError::Lichess(err)) => Err(LichessError::Move(MoveError::from_body(&err))),
+            res => res,
+        }
+    }
+
+    async fn play_resign(&self, play_as: PlayAs, id: &str) -> Response<bool> {
+        self.require_scope(play_as.scope()).await?;
+
+        let prefix = play_as.prefix();
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("{prefix}/game/{id}/resign"), String::new())
+            .await?;
+
+        let endpoint = match play_as {
+            PlayAs::Board => "board/game/{id}/resign",
+            PlayAs::Bot => "bot/game/{id}/resign",
+        };
+
+        Lichess::parse_ok(endpoint, res)
+    }
+
+    async fn play_abort(&self, play_as: PlayAs, id: &str) -> Response<bool> {
+        self.require_scope(play_as.scope()).await?;
+
+        let prefix = play_as.prefix();
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("{prefix}/game/{id}/abort"), String::new())
+            .await?;
+
+        let endpoint = match play_as {
+            PlayAs::Board => "board/game/{id}/abort",
+            PlayAs::Bot => "bot/game/{id}/abort",
+        };
+
+        Lichess::parse_ok(endpoint, res)
+    }
+
+    async fn play_chat(
+        &self,
+        play_as: PlayAs,
+        id: &str,
+        room: ChatRoom,
+        text: &str,
+    ) -> Response<bool> {
+        self.require_scope(play_as.scope()).await?;
+
+        let prefix = play_as.prefix();
+        let body = Lichess::encode_form(&[("room", room.as_str()), ("text", text)]);
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("{prefix}/game/{id}/chat"), body)
+            .await?;
+
+        let endpoint = match play_as {
+            PlayAs::Board => "board/game/{id}/chat",
+            PlayAs::Bot => "bot/game/{id}/chat",
+        };
+
+        Lichess::parse_ok(endpoint, res)
+    }
+
+    /// Export a game as PGN
+    /// Requires no scopes
+    pub async fn export_game(&self, id: &str, opts: ExportOptions) -> Response<String> {
+        let id = Lichess::encode_path(id);
+        let url = format!(
+            "{}?{}",
+            self.site_url(&format!("game/export/{id}")),
+            Lichess::encode_form(&opts.query())
+        );
+
+        self.get_raw_accept(&url, "application/x-chess-pgn").await
+    }
+
+    /// Upload a game from its PGN, e.g. to analyse a game played over the board
+    /// Requires no scopes
+    pub async fn import_game(&self, pgn: &str) -> Response<ImportedGame> {
+        match self
+            .post_api("import", Lichess::encode_form(&[("pgn", pgn)]))
+            .await
+        {
+            Ok(res) => Ok(serde_json::from_value(res)?),
+            Err(e) if e.status() == Some(400) => Err(LichessError::InvalidInput(format!(
+                "lichess couldn't read the PGN ({e})"
+            ))),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stream every game a user has played, one at a time; this can be
+    /// thousands of games, so nothing is buffered
+    /// Requires no scopes
+    pub async fn export_user_games(
+        &self,
+        username: &str,
+        opts: UserGamesOptions,
+    ) -> Response<impl Stream<Item = Value>> {
+        let username = Lichess::encode_path(username);
+        let url = self.api_url_query(&format!("games/user/{username}"), &opts.query());
+        self.ndjson(&url).await
+    }
+
+    /// Export up to 300 games by id, streaming each one as it's read
+    /// Requires no scopes
+    pub async fn games_by_ids(
+        &self,
+        ids: &[&str],
+        opts: ExportOptions,
+    ) -> Response<impl Stream<Item = Value>> {
+        if ids.len() > 300 {
+            return Err(LichessError::InvalidInput(format!(
+                "can't export more than 300 games at once, got {}",
+                ids.len()
+            )));
+        }
+
+        let url = self.api_url_query("games/export/_ids", &opts.query());
+        self.post_ndjson(&url, ids.join(",")).await
+    }
+
+    /// Look up the cloud evaluation of a position, or `None` if it hasn't been analysed
+    /// Requires no scopes
+    pub async fn cloud_eval(
+        &self,
+        fen: &str,
+        multi_pv: u8,
+        variant: Option<&str>,
+    ) -> Response<Option<CloudEval>> {
+        let multi_pv = multi_pv.to_string();
+        let mut query = vec![("fen", fen), ("multiPv", multi_pv.as_str())];
+
+        if let Some(variant) = variant {
+            query.push(("variant", variant));
+        }
+
+        match self.get_api_query("cloud-eval", &query).await {
+            Ok(res) => Ok(Some(serde_json::from_value(res)?)),
+            Err(e) if e.status() == Some(404) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Query the masters opening explorer for a position
+    /// Requires no scopes
+    pub async fn explorer_masters(
+        &self,
+        fen: &str,
+        moves: u8,
+        top_games: u8,
+    ) -> Response<ExplorerResult> {
+        let query = Lichess::encode_form(&[
+            ("fen", fen.to_string()),
+            ("moves", moves.to_string()),
+            ("topGames", top_games.to_string()),
+        ]);
+
+        // the explorer lives on its own host, not under the api
+        let res = self
+            .get(&format!("https://explorer.lichess.ovh/masters?{query}"))
+            .await?;
+
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Probe the endgame tablebase for a position of up to 7 pieces
+    /// Requires no scopes
+    pub async fn tablebase(&self, fen: &str) -> Response<TablebaseResult> {
+        let pieces = fen
+            .split(' ')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .filter(char::is_ascii_alphabetic)
+            .count();
+
+        if pieces > Lichess::TABLEBASE_PIECES {
+            return Err(LichessError::TooManyPieces { pieces });
+        }
+
+        let query = Lichess::encode_form(&[("fen", fen)]);
+
+        // the tablebase lives on its own host, not under the api
+        let res = self
+            .get(&format!("https://tablebase.lichess.ovh/standard?{query}"))
+            .await?;
+
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Get an arena tournament, including its current standings
+    /// Requires no scopes
+    pub async fn arena(&self, id: &str) -> Response<Value> {
+        let id = Lichess::encode_path(id);
+        self.get_api(&format!("tournament/{id}")).await
+    }
+
+    /// Join an arena tournament, giving its `password` if it's private
+    /// Requires `tournament:write` scope
+    pub async fn join_arena(&self, id: &str, password: Option<&str>) -> Response<bool> {
+        self.require_scope("tournament:write").await?;
+
+        let body = match password {
+            Some(password) => Lichess::encode_form(&[("password", password)]),
+            None => String::new(),
+        };
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("tournament/{id}/join"), body)
+            .await?;
+
+        Lichess::parse_ok("tournament/{id}/join", res)
+    }
+
+    /// Leave an arena tournament, or pause if it has already started
+    /// Requires `tournament:write` scope
+    pub async fn withdraw_arena(&self, id: &str) -> Response<bool> {
+        self.require_scope("tournament:write").await?;
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("tournament/{id}/withdraw"), String::new())
+            .await?;
+
+        Lichess::parse_ok("tournament/{id}/withdraw", res)
+    }
+
+    /// Stream the results of an arena tournament, best first, optionally only the top `nb`
+    /// Requires no scopes
+    pub async fn arena_results(
+        &self,
+        id: &str,
+        nb: Option<u32>,
+    ) -> Response<impl Stream<Item = ArenaStanding>> {
+        let nb = nb.map(|nb| nb.to_string());
+        let query: Vec<_> = nb.iter().map(|nb| ("nb", nb.as_str())).collect();
+
+        let id = Lichess::encode_path(id);
+        let url = self.api_url_query(&format!("tournament/{id}/results"), &query);
+        self.ndjson(&url).await
+    }
+
+    /// Get a Swiss tournament
+    /// Requires no scopes
+    pub async fn swiss(&self, id: &str) -> Response<Value> {
+        let id = Lichess::encode_path(id);
+        self.get_api(&format!("swiss/{id}")).await
+    }
+
+    /// Join a Swiss tournament, giving its `password` if it's private
+    /// Requires `tournament:write` scope
+    pub async fn join_swiss(&self, id: &str, password: Option<&str>) -> Response<bool> {
+        self.require_scope("tournament:write").await?;
+
+        let body = match password {
+            Some(password) => Lichess::encode_form(&[("password", password)]),
+            None => String::new(),
+        };
+
+        let id = Lichess::encode_path(id);
+        let res = self.post_api(&format!("swiss/{id}/join"), body).await?;
+
+        Lichess::parse_ok("swiss/{id}/join", res)
+    }
+
+    /// Leave a Swiss tournament, or pause if it has already started
+    /// Requires `tournament:write` scope
+    pub async fn withdraw_swiss(&self, id: &str) -> Response<bool> {
+        self.require_scope("tournament:write").await?;
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post_api(&format!("swiss/{id}/withdraw"), String::new())
+            .await?;
+
+        Lichess::parse_ok("swiss/{id}/withdraw", res)
+    }
+
+    /// Stream the results of a Swiss tournament, best first, optionally only the top `nb`
+    /// Requires no scopes
+    pub async fn swiss_results(
+        &self,
+        id: &str,
+        nb: Option<u32>,
+    ) -> Response<impl Stream<Item = SwissStanding>> {
+        let nb = nb.map(|nb| nb.to_string());
+        let query: Vec<_> = nb.iter().map(|nb| ("nb", nb.as_str())).collect();
+
+        let id = Lichess::encode_path(id);
+        let url = self.api_url_query(&format!("swiss/{id}/results"), &query);
+        self.ndjson(&url).await
+    }
+
+    /// Get a team
+    /// Requires no scopes
+    pub async fn team(&self, id: &str) -> Response<Value> {
+        let id = Lichess::encode_path(id);
+        self.get_api(&format!("team/{id}")).await
+    }
+
+    /// Stream the members of a team, most recent first
+    /// Requires no scopes
+    pub async fn team_members(&self, id: &str) -> Response<impl Stream<Item = Value>> {
+        let id = Lichess::encode_path(id);
+        self.ndjson(&self.api_url(&format!("team/{id}/users")))
+            .await
+    }
+
+    /// Join a team, or ask to if it needs approval; `message` is for the team's
+    /// leaders, and `password` is needed for some teams
+    /// Requires `team:write` scope
+    pub async fn join_team(
+        &self,
+        id: &str,
+        message: Option<&str>,
+        password: Option<&str>,
+    ) -> Response<bool> {
+        self.require_scope("team:write").await?;
+
+        let mut body = Vec::new();
+
+        if let Some(message) = message {
+            body.push(("message", message));
+        }
+
+        if let Some(password) = password {
+            body.push(("password", password));
+        }
+
+        let id = Lichess::encode_path(id);
+        // joining and leaving live outside of the api
+        let res = self
+            .post(
+                &self.site_url(&format!("team/{id}/join")),
+                Lichess::encode_form(&body),
+            )
+            .await?;
+
+        Lichess::parse_ok("team/{id}/join", res)
+    }
+
+    /// Leave a team
+    /// Requires `team:write` scope
+    pub async fn leave_team(&self, id: &str) -> Response<bool> {
+        self.require_scope("team:write").await?;
+
+        let id = Lichess::encode_path(id);
+        let res = self
+            .post(&self.site_url(&format!("team/{id}/quit")), String::new())
+            .await?;
+
+        Lichess::parse_ok("team/{id}/quit", res)
+    }
+
+    /// Get the daily puzzle
+    /// Requires no scopes, and works without a token
+    pub async fn daily_puzzle(&self) -> Response<Puzzle> {
+        let res = self.get_api("puzzle/daily").await?;
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Get a puzzle by its id
+    /// Requires no scopes, and works without a token
+    pub async fn puzzle(&self, id: &str) -> Response<Puzzle> {
+        let id = Lichess::encode_path(id);
+        let res = self.get_api(&format!("puzzle/{id}")).await?;
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Stream your puzzle history, most recent first, up to `max` entries
+    /// Requires `puzzle:read` scope
+    pub async fn puzzle_activity(&self, max: Option<u32>) -> Response<impl Stream<Item = Value>> {
+        self.require_scope("puzzle:read").await?;
+
+        let max = max.map(|max| max.to_string());
+        let query: Vec<_> = max.iter().map(|max| ("max", max.as_str())).collect();
+
+        self.ndjson(&self.api_url_query("puzzle/activity", &query))
+            .await
+    }
+
+    /// Get your puzzle performance over the last `days` days, broken down by theme
+    /// Requires `puzzle:read` scope
+    pub async fn puzzle_dashboard(&self, days: u32) -> Response<Value> {
+        self.require_scope("puzzle:read").await?;
+
+        self.get_api(&format!("puzzle/dashboard/{days}")).await
+    }
+
+    /// Get the simuls that are pending, created, started, or recently finished,
+    /// each under its own key
+    /// Requires no scopes
+    pub async fn simuls(&self) -> Response<Value> {
+        self.get_api("simul").await
+    }
+
+    /// Get the game currently featured on every TV channel
+    /// Requires no scopes
+    pub async fn tv_channels(&self) -> Response<Value> {
+        self.get_api("tv/channels").await
+    }
+
+    /// Follow the featured game of a TV channel, e.g. `blitz` or `bot`
+    /// Requires no scopes
+    pub async fn tv_feed(&self, channel: &str) -> Response<impl Stream<Item = Value>> {
+        let channel = Lichess::encode_path(channel);
+        self.ndjson(&self.api_url(&format!("tv/{channel}/feed")))
+            .await
+    }
+
+    /// Follow the featured game of a TV channel, parsed into `TvEvent`s
+    /// Requires no scopes
+    pub async fn tv_events(&self, channel: &str) -> Response<impl Stream<Item = TvEvent>> {
+        let channel = Lichess::encode_path(channel);
+        self.ndjson(&self.api_url(&format!("tv/{channel}/feed")))
+            .await
+    }
+
+    /// Stream the official broadcasts, ongoing and upcoming first
+    /// Requires no scopes
+    pub async fn official_broadcasts(&self) -> Response<impl Stream<Item = Value>> {
+        self.ndjson(&self.api_url("broadcast")).await
+    }
+
+    /// Get every game of a broadcast round as PGN
+    /// Requires no scopes
+    pub async fn broadcast_round(&self, id: &str) -> Response<String> {
+        let id = Lichess::encode_path(id);
+        self.get_raw_accept(
+            &self.api_url(&format!("broadcast/round/{id}.pgn")),
+            "application/x-chess-pgn",
+        )
+        .await
+    }
+
+    /// Export every chapter of a study as PGN
+    /// Requires no scopes, or `study:read` for private studies
+    pub async fn export_study(&self, study_id: &str) -> Response<String> {
+        let study_id = Lichess::encode_path(study_id);
+        self.get_raw_accept(
+            &self.api_url(&format!("study/{study_id}.pgn")),
+            "application/x-chess-pgn",
+        )
+        .await
+    }
+
+    /// Export one chapter of a study as PGN
+    /// Requires no scopes, or `study:read` for private studies
+    pub async fn export_study_chapter(&self, study_id: &str, chapter_id: &str) -> Response<String> {
+        let study_id = Lichess::encode_path(study_id);
+        let chapter_id = Lichess::encode_path(chapter_id);
+        self.get_raw_accept(
+            &self.api_url(&format!("study/{study_id}/{chapter_id}.pgn")),
+            "application/x-chess-pgn",
+        )
+        .await
+    }
+
+    /// Add a PGN to a study, one chapter per game in it; `name` is used for
+    /// chapters whose PGN has no `Event` tag
+    /// Requires `study:write` scope
+    pub async fn import_pgn_to_study(
+        &self,
+        study_id: &str,
+        pgn: &str,
+        name: &str,
+    ) -> Response<Value> {
+        self.require_scope("study:write").await?;
+
+        let study_id = Lichess::encode_path(study_id);
+        self.post_api(
+            &format!("study/{study_id}/import-pgn"),
+            Lichess::encode_form(&[("pgn", pgn), ("name", name)]),
+        )
+        .await
+    }
+
+    /// Watch up to 500 games at once, first getting each game's state and then its
+    /// moves as they're played; `stream_id` is yours to pick, and lets you add
+    /// games to the stream later
+    /// Requires no scopes
+    pub async fn stream_games_by_ids(
+        &self,
+        stream_id: &str,
+        game_ids: &[&str],
+    ) -> Response<impl Stream<Item = Value>> {
+        if game_ids.len() > 500 {
+            return Err(LichessError::InvalidInput(format!(
+                "can't stream more than 500 games, got {}",
+                game_ids.len()
+            )));
+        }
+
+        let stream_id = Lichess::encode_path(stream_id);
+        self.post_ndjson(
+            &self.api_url(&format!("stream/games/{stream_id}")),
+            game_ids.join(","),
+        )
+        .await
+    }
+
+    /// Follow a player live: yields their current game, then every game as it
+    /// starts or finishes. Unlike `export_user_games`, which dumps the games
+    /// they've already played, this stays open until dropped
+    /// Requires no scopes
+    pub async fn stream_user_current_games(
+        &self,
+        username: &str,
+    ) -> Response<impl Stream<Item = Value>> {
+        self.post_ndjson(
+            &self.api_url_query("stream/games-by-users", &[("withCurrentGames", "true")]),
+            username.to_string(),
+        )
+        .await
+    }
+
+    /// Get a stream from a server
+    /// Streams ignore `with_timeout`, since they stay open indefinitely
+    pub async fn stream(&self, url: &str) -> Response<impl Stream<Item = String>> {
+        let res = self.stream_results(url).await?;
+        Ok(Lichess::skip_errors(res))
+    }
+
+    /// Post to a server and read back a stream of lines; the body is sent as
+    /// `text/plain`, which is what Lichess' streaming endpoints expect
+    pub async fn post_stream(
+        &self,
+        url: &str,
+        body: String,
+    ) -> Response<impl Stream<Item = String>> {
+        let res = self.open_stream(self.plain_post(url, body)).await?;
+
+        Ok(Lichess::skip_errors(Lichess::text_lines(
+            res,
+            self.max_line_length,
+        )))
+    }
+
+    /// Get a stream from a server, yielding the error if it breaks partway through,
+    /// so that it can be told apart from the stream ending normally
+    pub async fn stream_results(
+        &self,
+        url: &str,
+    ) -> Response<impl Stream<Item = Response<String>>> {
+        let res = self.open_stream(self.hclient.get(url)).await?;
+        Ok(Lichess::text_lines(res, self.max_line_length))
+    }
+
+    /// Get an ndjson stream from a server.
+    ///
+    /// Blank lines are skipped, and so are the `{}` keepalives some streams send,
+    /// unless `LichessBuilder::skip_keepalives` is turned off
+    pub async fn ndjson<T: DeserializeOwned>(&self, url: &str) -> Response<impl Stream<Item = T>> {
+        let res = self.ndjson_results(url).await?;
+        Ok(Lichess::skip_errors(res))
+    }
+
+    /// Post to a server and read back an ndjson stream, see `post_stream`
+    pub async fn post_ndjson<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        body: String,
+    ) -> Response<impl Stream<Item = T>> {
+        let res = self
+            .open_stream(
+                self.plain_post(url, body)
+                    .header(reqwest::header::ACCEPT, "application/x-ndjson"),
+            )
+            .await?;
+
+        Ok(Lichess::skip_errors(Lichess::ndjson_lines(
+            res,
+            self.skip_keepalives,
+            self.max_line_length,
+        )))
+    }
+
+    /// A POST request with a `text/plain` body, for streaming endpoints
+    fn plain_post(&self, url: &str, body: String) -> reqwest::RequestBuilder {
+        self.hclient
+            .post(url)
+            .body(body)
+            .header(reqwest::header::CONTENT_TYPE, "text/plain")
+    }
+
+    /// Drop (and log) the items of a stream that failed
+    fn skip_errors<T>(items: impl Stream<Item = Response<T>>) -> impl Stream<Item = T> {
+        Box::pin(
+            items
+                .take_while(|item| {
+                    let keep_going = match item {
+                        Err(e @ LichessError::LineTooLong { .. }) => {
+                            log::warn!("ending stream: {e}");
+                            false
+                        }
+                        _ => true,
+                    };
+                    futures_util::future::ready(keep_going)
+                })
+                .filter_map(|item| async move {
+                    match item {
+                        Ok(item) => Some(item),
+                        Err(e) => {
+                            log::warn!("dropping stream item: {e}");
+                            None
+                        }
+                    }
+                }),
+        )
+    }
+
+    /// Get an ndjson stream from a server, yielding lines that fail to parse as errors
+    pub async fn ndjson_results<T: DeserializeOwned>(
+        &self,
+        url: &str,
+    ) -> Response<impl Stream<Item = Response<T>>> {
+        let res = self.open_ndjson(url).await?;
+        Ok(Lichess::ndjson_lines(
+            res,
+            self.skip_keepalives,
+            self.max_line_length,
+        ))
+    }
+
+    /// Start a streaming request without reading any of it, turning bad status
+    /// codes into an `ApiError` up front
+    async fn open_stream(&self, req: reqwest::RequestBuilder) -> Response<reqwest::Response> {
+        let res = self.execute(self.authed(req).build()?).await?;
+        let status = res.status().as_u16();
+
+        if res.status().is_success() {
+            Ok(res)
+        } else {
+            Err(Lichess::api_error(status, res.text().await?))
+        }
+    }
+
+    /// Start an ndjson request, without reading any of it
+    async fn open_ndjson(&self, url: &str) -> Response<reqwest::Response> {
+        self.open_stream(
+            self.hclient
+                .get(url)
+                .header(reqwest::header::ACCEPT, "application/x-ndjson"),
+        )
+        .await
+    }
+
+    /// Split a response into its non-blank lines, none longer than `max_len`;
+    /// owns the response, so the stream doesn't borrow the client
+    fn text_lines(res: reqwest::Response, max_len: usize) -> impl Stream<Item = Response<String>> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("stream", endpoint = res.url().path());
+
+        let res = StreamReader::new(res.bytes_stream().map_err(Lichess::convert_err));
+
+        let lines = FramedRead::new(res, LinesCodec::new_with_max_length(max_len)).filter_map(
+            move |l| async move {
+                match l {
+                    Ok(line) if line.trim().is_empty() => None,
+                    Ok(line) => Some(Ok(line)),
+                    Err(LinesCodecError::MaxLineLengthExceeded) => {
+                        Some(Err(LichessError::LineTooLong { limit: max_len }))
+                    }
+                    Err(LinesCodecError::Io(e)) => Some(Err(e.into())),
+                }
+            },
+        );
+
+        // the stream owns the span, so it stays open until the stream is dropped
+        #[cfg(feature = "tracing")]
+        let lines = lines.inspect(move |line| {
+            if let Err(e) = line {
+                span.in_scope(|| tracing::warn!(error = %e, "stream item failed"));
+            }
+        });
+
+        Box::pin(lines)
+    }
+
+    /// Parse an ndjson response line by line, optionally skipping `{}` keepalives
+    fn ndjson_lines<T: DeserializeOwned>(
+        res: reqwest::Response,
+        skip_keepalives: bool,
+        max_len: usize,
+    ) -> impl Stream<Item = Response<T>> {
+        Box::pin(
+            Lichess::text_lines(res, max_len).filter_map(move |line| async move {
+                match line {
+                    Ok(line) if skip_keepalives && line.trim() == "{}" => None,
+                    Ok(line) => {
+                        Some(serde_json::from_str(line.as_str()).map_err(LichessError::from))
+                    }
+                    Err(e) => Some(Err(e)),
+                }
+            }),
+        )
+    }
+
+    /// Get a listener to the user events stream
+    /// Requires `challenge:read bot:play board:play` scopes
+    pub async fn events<T: DeserializeOwned>(&self) -> Response<impl Stream<Item = T>> {
+        self.ndjson(&self.api_url("stream/event")).await
+    }
+
+    /// Get a listener to the user events stream that reconnects by itself when
+    /// it drops, backing off exponentially up to a minute between attempts.
+    /// `IncomingEvent::Reconnected` is yielded after every reconnect. The stream
+    /// ends if Lichess refuses it with a 4xx other than 429, e.g. for a revoked
+    /// token, since retrying wouldn't help
+    /// Requires `challenge:read bot:play board:play` scopes
+    pub async fn events_resilient(&self) -> impl Stream<Item = IncomingEvent> {
+        struct State {
+            client: Lichess,
+            url: String,
+            events: Option<Pin<Box<dyn Stream<Item = Response<IncomingEvent>> + Send>>>,
+            backoff: Duration,
+            dropped: bool,
+        }
+
+        let state = State {
+            client: self.clone(),
+            url: self.api_url("stream/event"),
+            events: None,
+            backoff: Duration::ZERO,
+            dropped: false,
+        };
+
+        Box::pin(futures_util::stream::unfold(
+            state,
+            |mut state| async move {
+                loop {
+                    if let Some(events) = &mut state.events {
+                        match events.next().await {
+                            Some(Ok(event)) => {
+                                state.backoff = Duration::ZERO;
+                                return Some((event, state));
+                            }
+                            Some(Err(LichessError::Json(e))) => {
+                                log::warn!("dropping ndjson line: {e}");
+                                continue;
+                            }
+                            Some(Err(e)) => log::warn!("event stream failed: {e}"),
+                            None => log::warn!("event stream ended"),
+                        }
+
+                        state.events = None;
+                        state.dropped = true;
+                    }
+
+                    if !state.backoff.is_zero() {
+                        tokio::time::sleep(state.backoff).await;
+                    }
+                    state.backoff =
+                        (state.backoff * 2).clamp(Duration::from_secs(1), Duration::from_secs(60));
+
+                    match state.client.open_ndjson(&state.url).await {
+                        Ok(res) => {
+                            state.events = Some(Box::pin(Lichess::ndjson_lines(
+                                res,
+                                state.client.skip_keepalives,
+                                state.client.max_line_length,
+                            )));
+
+                            if std::mem::take(&mut state.dropped) {
+                                #[cfg(feature = "tracing")]
+                                tracing::info!("reconnected to the event stream");
+
+                                return Some((IncomingEvent::Reconnected, state));
+                            }
+                        }
+                        // a bad or revoked token won't fix itself, so don't retry forever
+                        Err(e)
+                            if matches!(e.status(), Some(400..=499)) && e.status() != Some(429) =>
+                        {
+                            log::error!("giving up on the event stream: {e}");
+                            return None;
+                        }
+                        Err(e) => log::warn!("couldn't open event stream: {e}"),
+                    }
+                }
+            },
+        ))
+    }
+
+    /// Get a listener to the user events stream, parsed into `IncomingEvent`s
+    /// Requires `challenge:read bot:play board:play` scopes
+    pub async fn typed_events(&self) -> Response<impl Stream<Item = IncomingEvent>> {
+        self.events().await
+    }
+
+    /// Get a listener to a board event stream
+    /// Requires `board:play` scope
+    pub async fn board<T: DeserializeOwned>(&self, id: &str) -> Response<impl Stream<Item = T>> {
+        self.require_scope("board:play").await?;
+
+        let id = Lichess::encode_path(id);
+        self.ndjson(&self.api_url(&format!("board/game/stream/{id}")))
+            .await
+    }
+
+    /// Get a listener to a board event stream, parsed into `BoardEvent`s
+    /// Requires `board:play` scope
+    pub async fn board_events(&self, id: &str) -> Response<impl Stream<Item = BoardEvent>> {
+        self.require_scope("board:play").await?;
+
+        let id = Lichess::encode_path(id);
+        self.ndjson(&self.api_url(&format!("board/game/stream/{id}")))
+            .await
+    }
+
+    /// Play a game with `strategy`, which is asked for a move (in UCI) whenever
+    /// it's your turn. If it returns `None` instead, it's asked again every
+    /// second until it moves or the game changes. Returns the game's final
+    /// state once it ends, with its `winner` unless it was drawn
+    /// Requires `board:play` scope
+    pub async fn play_game<F>(&self, game_id: &str, mut strategy: F) -> Response<GameState>
+    where
+        F: FnMut(&GameState) -> Option<String>,
+    {
+        let mut game = GameTracker::new(self).await?;
+        let mut events = Box::pin(self.board_events(game_id).await?);
+
+        // the state `strategy` passed on while it was our turn, to ask again
+        let mut waiting: Option<GameState> = None;
+
+        loop {
+            // `None` when it's time to ask `strategy` again
+            let event = if waiting.is_some() {
+                tokio::select! {
+                    event = events.next() => Some(event),
+                    _ = tokio::time::sleep(Duration::from_secs(1)) => None,
+                }
+            } else {
+                Some(events.next().await)
+            };
+
+            let state = match event {
+                None => waiting.take().unwrap(),
+                Some(event) => match game.update(event)? {
+                    Some(state) => state,
+                    None => continue,
+                },
+            };
+            waiting = None;
+
+            if GameTracker::ended(&state) {
+                return Ok(state);
+            }
+
+            if !game.my_turn(&state) {
+                continue;
+            }
+
+            match strategy(&state) {
+                Some(m) => match self.make_move(game_id, &m, false).await {
+                    Ok(_) | Err(LichessError::Move(MoveError::NotYourTurn)) => {}
+                    Err(e) => return Err(e),
+                },
+                None => waiting = Some(state),
+            }
+        }
+    }
+
+    /// Percent-encode key/value pairs into a form body
+    fn encode_form<K: AsRef<str>, V: AsRef<str>>(pairs: &[(K, V)]) -> String {
+        form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(pairs)
+            .finish()
+    }
+
+    /// Percent-encode one path segment, e.g. a username, id or move, so that a
+    /// `/`, `?` or `#` in it can't change which endpoint is hit
+    fn encode_path(segment: &str) -> String {
+        let mut encoded = String::with_capacity(segment.len());
+        for b in segment.bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    encoded.push(b as char)
+                }
+                _ => encoded.push_str(&format!("%{b:02X}")),
+            }
+        }
+        encoded
+    }
+
+    /// Extract the `ok` field from a JSON response
+    fn parse_ok(endpoint: &'static str, res: Value) -> Response<bool> {
+        if let Value::Object(err) = &res["error"] {
+            return Err(LichessError::Lichess(Value::Object(err.clone())));
+        }
+
+        if let Value::Bool(ok) = &res["ok"] {
+            return Ok(*ok);
+        }
+
+        Err(LichessError::Unexpected {
+            endpoint,
+            body: res,
+        })
+    }
+
+    // TODO: consider other ErrorKind's
+    fn convert_err(e: reqwest::Error) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::Other, e)
+    }
+}
+
+/// Follows one of your games through its board events, for `Lichess::play_game`
+/// and `PremoveQueue::run`: which side you play, and whose turn it is
+struct GameTracker {
+    me: String,
+    /// Whether you're white, and whether white had the first move; known
+    /// once the `GameFull` arrives
+    sides: Option<(bool, bool)>,
+}
+
+impl GameTracker {
+    async fn new(client: &Lichess) -> Response<GameTracker> {
+        Ok(GameTracker {
+            me: client.account_typed().await?.id,
+            sides: None,
+        })
+    }
+
+    /// Take in the next event, returning the game's state if it carries one;
+    /// the stream ending before the game does is an error
+    fn update(&mut self, event: Option<BoardEvent>) -> Response<Option<GameState>> {
+        match event {
+            Some(BoardEvent::GameFull(full)) => {
+                let white = full.white.id.as_deref() == Some(self.me.as_str());
+                let white_first = full.initial_fen.split_whitespace().nth(1) != Some("b");
+                self.sides = Some((white, white_first));
+                Ok(Some(full.state))
+            }
+            Some(BoardEvent::GameState(state)) => Ok(Some(state)),
+            Some(_) => Ok(None),
+            None => Err(LichessError::Unexpected {
+                endpoint: "board/game/stream/{id}",
+                body: Value::Null,
+            }),
+        }
+    }
+
+    /// Whether it's your move in `state`; never before the `GameFull`
+    fn my_turn(&self, state: &GameState) -> bool {
+        let Some((white, white_first)) = self.sides else {
+            return false;
+        };
+
+        let white_to_move = state.moves.split_whitespace().count().is_multiple_of(2) == white_first;
+        white_to_move == white
     }
-}
 
-impl fmt::Display for ApiError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(
-            f,
-            "HTTP request returned bad code: {}\nmessage: {:#?}",
-            self.code, self.msg
-        )
+    fn ended(state: &GameState) -> bool {
+        !matches!(state.status.as_str(), "created" | "started")
     }
 }
 
-impl Error for ApiError {}
-
-#[derive(Clone)]
-pub struct Lichess {
-    key: String,
-    hclient: reqwest::Client,
+/// Moves to play in a game as soon as it's your turn, queued up ahead of time.
+///
+/// Clones share one queue, so a task can keep `push`ing while another `run`s
+/// it
+#[derive(Debug, Clone, Default)]
+pub struct PremoveQueue {
+    moves: Arc<std::sync::Mutex<VecDeque<String>>>,
+    pushed: Arc<tokio::sync::Notify>,
 }
 
-impl Lichess {
-    /// Make a new client with a Lichess API key
-    pub fn new(key: String) -> Lichess {
-        Lichess {
-            key,
-            hclient: reqwest::Client::new(),
-        }
+impl PremoveQueue {
+    pub fn new() -> PremoveQueue {
+        PremoveQueue::default()
     }
 
-    /// Get a plaintext response from a server
-    pub async fn get_raw(&self, url: String) -> Response<String> {
-        let res = self
-            .hclient
-            .get(url)
-            .bearer_auth(self.key.clone())
-            .send()
-            .await?;
+    /// Queue a move, in UCI, to play after the ones already queued
+    pub fn push(&self, uci: impl Into<String>) {
+        self.moves.lock().unwrap().push_back(uci.into());
+        self.pushed.notify_one();
+    }
 
-        let status = res.status().as_u16();
-        let msg = res.text().await?;
+    /// Drop every queued move
+    pub fn clear(&self) {
+        self.moves.lock().unwrap().clear();
+    }
 
-        match status {
-            200 | 201 => Ok(msg),
+    pub fn len(&self) -> usize {
+        self.moves.lock().unwrap().len()
+    }
 
-            _ => {
-                if msg.is_empty() {
-                    return Err(Box::new(ApiError::new(status, Value::Null)));
-                }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Follow a game, playing the queued moves whenever it's your turn, until it
+    /// ends; returns its final state, with its `winner` unless it was drawn. A
+    /// premove Lichess refuses as illegal, e.g. after the opponent's reply, is
+    /// cancelled along with the rest of the queue
+    /// Requires `board:play` scope
+    pub async fn run(&self, client: &Lichess, game_id: &str) -> Response<GameState> {
+        let mut game = GameTracker::new(client).await?;
+        let mut events = Box::pin(client.board_events(game_id).await?);
+
+        let mut state: Option<GameState> = None;
+        // how many moves had been played when we last sent one, so a push
+        // can't send a second move before the first comes back
+        let mut sent_at = None;
+
+        loop {
+            tokio::select! {
+                event = events.next() => match game.update(event)? {
+                    Some(next) => state = Some(next),
+                    None => continue,
+                },
+                _ = self.pushed.notified() => {}
+            }
+
+            let Some(current) = &state else {
+                continue;
+            };
+
+            if GameTracker::ended(current) {
+                return Ok(current.clone());
+            }
 
-                return Err(Box::new(ApiError::new(
-                    status,
-                    serde_json::from_str(msg.as_str())?,
-                )));
+            let played = current.moves.split_whitespace().count();
+            let Some(next) = self.due(game.my_turn(current), played, sent_at) else {
+                continue;
+            };
+
+            sent_at = Some(played);
+            match client.make_move(game_id, &next, false).await {
+                // it stays queued until Lichess takes it, unless `clear`ed meanwhile
+                Ok(_) => {
+                    let mut moves = self.moves.lock().unwrap();
+                    if moves.front() == Some(&next) {
+                        moves.pop_front();
+                    }
+                }
+                Err(LichessError::Move(MoveError::NotYourTurn)) => {}
+                // the rest of the queue was planned around this move
+                Err(LichessError::Move(MoveError::IllegalMove)) => {
+                    log::warn!("cancelling premoves from {next}, it isn't legal anymore");
+                    self.clear();
+                }
+                Err(e) => return Err(e),
             }
         }
     }
 
-    /// Get and parse a JSON response from a server
-    pub async fn get(&self, url: String) -> Response<Value> {
-        Ok(serde_json::from_str(self.get_raw(url).await?.as_str())?)
+    /// The queued move to send now, without taking it off the queue; `None` if
+    /// it's not your turn, nothing is queued, or a move was already sent after
+    /// `played` moves
+    fn due(&self, my_turn: bool, played: usize, sent_at: Option<usize>) -> Option<String> {
+        if !my_turn || sent_at == Some(played) {
+            return None;
+        }
+
+        self.moves.lock().unwrap().front().cloned()
     }
+}
 
-    /// Post to a server
-    pub async fn post_raw(&self, url: String, body: String) -> Response<String> {
-        let res = self
-            .hclient
-            .post(url)
-            .bearer_auth(self.key.clone())
-            .body(body)
-            .header(
-                "content-type",
-                String::from("application/x-www-form-urlencoded"),
-            )
-            .send()
-            .await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let status = res.status().as_u16();
-        let msg = res.text().await?;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
 
-        match status {
-            200 | 201 => Ok(msg),
+    /// A raw HTTP response that closes its connection, so every request
+    /// gets a connection (and a reply) of its own
+    fn reply(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n{headers}\r\n{body}",
+            body.len()
+        )
+    }
 
-            _ => {
-                if msg.is_empty() {
-                    return Err(Box::new(ApiError::new(status, Value::Null)));
-                }
+    /// Serve `replies` in order, one per connection; see `mock_delayed`
+    async fn mock(replies: Vec<String>) -> (String, mpsc::UnboundedReceiver<String>) {
+        mock_delayed(replies.into_iter().map(|r| (Duration::ZERO, r)).collect()).await
+    }
+
+    /// Serve `replies` in order, one per connection, each after its delay.
+    /// Returns the server's url, and the requests it gets as they come in
+    async fn mock_delayed(
+        replies: Vec<(Duration, String)>,
+    ) -> (String, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            for (delay, reply) in replies {
+                let (mut conn, _) = listener.accept().await.unwrap();
+                let tx = tx.clone();
+
+                // on a task of its own, so a slow reply doesn't hold up the next
+                tokio::spawn(async move {
+                    let _ = tx.send(read_request(&mut conn).await);
+                    tokio::time::sleep(delay).await;
+                    let _ = conn.write_all(reply.as_bytes()).await;
+                    let _ = conn.shutdown().await;
+                });
+            }
+        });
+
+        (url, rx)
+    }
+
+    /// Read a request's head and its body, going by `Content-Length`
+    async fn read_request(conn: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut chunk = [0; 4096];
+
+        loop {
+            match conn.read(&mut chunk).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => request.extend_from_slice(&chunk[..n]),
+            }
 
-                return Err(Box::new(ApiError::new(
-                    status,
-                    serde_json::from_str(msg.as_str())?,
-                )));
+            let text = String::from_utf8_lossy(&request);
+            let Some(end) = text.find("\r\n\r\n") else {
+                continue;
+            };
+
+            let len: usize = text[..end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse().ok())?
+                })
+                .unwrap_or(0);
+
+            if request.len() >= end + 4 + len {
+                break;
             }
         }
+
+        String::from_utf8_lossy(&request).into_owned()
     }
 
-    /// Post to a server, returning json
-    pub async fn post(&self, url: String, body: String) -> Response<Value> {
-        Ok(serde_json::from_str(
-            self.post_raw(url, body).await?.as_str(),
-        )?)
+    /// The body of a request read by `mock`
+    fn body(request: &str) -> &str {
+        request.split_once("\r\n\r\n").map_or("", |(_, body)| body)
     }
 
-    /// Get a Lichess api endpoint
-    pub async fn get_api(&self, endpoint: String) -> Response<Value> {
-        self.get(format!("https://lichess.org/api/{endpoint}"))
-            .await
+    /// A client for a `mock` server, ignoring any proxy set in the environment
+    fn client(url: &str) -> LichessBuilder {
+        let hclient = reqwest::Client::builder().no_proxy().build().unwrap();
+        Lichess::builder("token").base_url(url).client(hclient)
     }
 
-    /// Post to a Lichess api endpoint, returning json
-    pub async fn post_api(&self, endpoint: String, body: String) -> Response<Value> {
-        self.post(format!("https://lichess.org/api/{endpoint}"), body)
-            .await
+    fn clock(limit: u32, increment: u32) -> ClockSettings {
+        ClockSettings {
+            is_correspondence: false,
+            days: 0,
+            limit,
+            increment,
+        }
     }
 
-    /// Post to a Lichess api endpoint
-    pub async fn post_api_raw(&self, endpoint: String, body: String) -> Response<String> {
-        self.post_raw(format!("https://lichess.org/api/{endpoint}"), body)
+    #[tokio::test]
+    async fn seek_body() {
+        let (url, mut requests) = mock(vec![reply("200 OK", "", "")]).await;
+        let lichess = client(&url).build();
+
+        let res = lichess
+            .seek(true, Some(Color::White), Some(clock(300, 3)), None)
+            .await;
+        assert_eq!(res.unwrap(), None);
+
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("POST /api/board/seek "));
+        assert_eq!(body(&request), "color=white&rated=true&time=5&increment=3");
+    }
+
+    #[tokio::test]
+    async fn rate_limit_retry() {
+        let (url, mut requests) = mock(vec![
+            reply("429 Too Many Requests", "Retry-After: 0\r\n", ""),
+            reply("200 OK", "", r#"{"ok":true}"#),
+        ])
+        .await;
+        let lichess = client(&url).rate_limit_retries(1).build();
+
+        // a `Retry-After` of 0 is taken as is, not as the 60 second default
+        let res = tokio::time::timeout(Duration::from_secs(5), lichess.get_api("account"))
             .await
+            .unwrap();
+        assert_eq!(res.unwrap(), serde_json::json!({ "ok": true }));
+        assert!(requests.recv().await.is_some());
+        assert!(requests.recv().await.is_some());
     }
 
-    /// Get the email of your account
-    /// Requires `email:read` scope
-    pub async fn email(&self) -> Response<String> {
-        let res = self.get_api("account/email".into()).await?;
+    #[tokio::test]
+    async fn rate_limit_retries_run_out() {
+        let limited = reply("429 Too Many Requests", "Retry-After: 0\r\n", "");
+        let (url, mut requests) = mock(vec![
+            limited.clone(),
+            limited.clone(),
+            limited,
+            reply("200 OK", "", r#"{"ok":true}"#),
+        ])
+        .await;
+        let lichess = client(&url).rate_limit_retries(2).build();
 
-        if let Value::Object(err) = &res["error"] {
-            return Err(format!("{err:?}").into());
-        }
+        let res = lichess.get_api("account").await;
+        assert!(matches!(
+            res,
+            Err(LichessError::RateLimited { retry_after: Some(after) }) if after.is_zero()
+        ));
 
-        if let Value::String(email) = &res["email"] {
-            return Ok(email.clone());
+        for _ in 0..3 {
+            requests.recv().await.unwrap();
         }
+        assert!(requests.try_recv().is_err());
+    }
 
-        unreachable!(
-            "INTERNAL ERROR: something has gone horribly wrong (in client.rs: `fn email`, line {})",
-            line!()
-        );
+    #[tokio::test]
+    async fn rate_limit_without_retries() {
+        let (url, _requests) = mock(vec![
+            reply("429 Too Many Requests", "Retry-After: 7\r\n", ""),
+            reply("429 Too Many Requests", "", ""),
+        ])
+        .await;
+        let lichess = client(&url).build();
+
+        let res = lichess.get_api("account").await;
+        assert!(matches!(
+            res,
+            Err(LichessError::RateLimited { retry_after: Some(after) })
+                if after == Duration::from_secs(7)
+        ));
+
+        let res = lichess.get_api("account").await;
+        assert!(matches!(
+            res,
+            Err(LichessError::RateLimited { retry_after: None })
+        ));
     }
 
-    /// Get your account details
-    /// Requires no scopes
-    pub async fn account(&self) -> Response<Value> {
-        self.get_api("account".to_string()).await
+    #[tokio::test]
+    async fn rate_limit_default_wait() {
+        let (url, _requests) = mock(vec![
+            reply("429 Too Many Requests", "", ""),
+            reply("200 OK", "", r#"{"ok":true}"#),
+        ])
+        .await;
+        let lichess = client(&url).rate_limit_retries(1).build();
+
+        // without `Retry-After`, the retry waits a minute, well past this
+        let res = tokio::time::timeout(Duration::from_secs(1), lichess.get_api("account")).await;
+        assert!(res.is_err());
     }
 
-    /// Challenge the AI
-    /// Requires `challenge:write` scope
-    pub async fn ai(
-        &self,
-        level: i32,
-        color: Color,
-        clock: ClockSettings,
-        initial: Option<String>,
-    ) -> Response<String> {
-        let mut body = format!("level={level}");
+    #[test]
+    fn encode_form_escapes() {
+        assert_eq!(
+            Lichess::encode_form(&[(
+                "fen",
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+            )]),
+            "fen=rnbqkbnr%2Fpppppppp%2F8%2F8%2F8%2F8%2FPPPPPPPP%2FRNBQKBNR+w+KQkq+-+0+1"
+        );
+        assert_eq!(
+            Lichess::encode_form(&[("text", "1+1 & more"), ("to", "you")]),
+            "text=1%2B1+%26+more&to=you"
+        );
+    }
 
-        if color == Color::White {
-            body.push_str("&color=white");
-        } else if color == Color::Black {
-            body.push_str("&color=black");
+    #[test]
+    fn seek_minutes() {
+        for (limit, minutes) in [
+            (0, "0"),
+            (15, "0.25"),
+            (30, "0.5"),
+            (45, "0.75"),
+            (90, "1.5"),
+            (60, "1"),
+            (180 * 60, "180"),
+        ] {
+            assert_eq!(Lichess::seek_minutes(limit).as_deref(), Some(minutes));
         }
 
-        if clock.is_correspondence {
-            body.push_str(format!("&days={}", clock.days).as_str());
-        } else {
-            body.push_str(format!("&clock.limit={}", clock.limit).as_str());
-            body.push_str(format!("&clock.increment={}", clock.increment).as_str());
+        for limit in [10, 100, 150, 181 * 60] {
+            assert_eq!(Lichess::seek_minutes(limit), None);
+            assert!(matches!(
+                Lichess::encode_clock(Some(&clock(limit, 0)), ClockStyle::Seek),
+                Err(LichessError::InvalidInput(_))
+            ));
         }
+    }
 
-        if let Some(fen) = initial {
-            body.push_str(format!("&fen={fen}").as_str());
+    #[test]
+    fn encode_clock_styles() {
+        let correspondence = ClockSettings {
+            is_correspondence: true,
+            days: 3,
+            limit: 300,
+            increment: 3,
+        };
+
+        for style in [ClockStyle::Challenge, ClockStyle::Seek] {
+            assert!(Lichess::encode_clock(None, style).unwrap().is_empty());
+            assert_eq!(
+                Lichess::encode_clock(Some(&correspondence), style).unwrap(),
+                [("days", String::from("3"))]
+            );
         }
 
-        let res = self.post_api(String::from("challenge/ai"), body).await?;
+        assert_eq!(
+            Lichess::encode_clock(Some(&clock(300, 3)), ClockStyle::Challenge).unwrap(),
+            [
+                ("clock.limit", String::from("300")),
+                ("clock.increment", String::from("3"))
+            ]
+        );
+        assert_eq!(
+            Lichess::encode_clock(Some(&clock(300, 3)), ClockStyle::Seek).unwrap(),
+            [
+                ("time", String::from("5")),
+                ("increment", String::from("3"))
+            ]
+        );
+        assert_eq!(
+            Lichess::encode_clock(Some(&clock(90, 0)), ClockStyle::Seek).unwrap(),
+            [
+                ("time", String::from("1.5")),
+                ("increment", String::from("0"))
+            ]
+        );
+    }
 
-        if let Value::Object(err) = &res["error"] {
-            return Err(format!("{err:?}").into());
-        }
+    #[tokio::test]
+    async fn ai_random_color() {
+        let (url, mut requests) =
+            mock(vec![reply("201 Created", "", r#"{"id":"abcd1234"}"#)]).await;
+        let lichess = client(&url).build();
 
-        if let Value::String(id) = &res["id"] {
-            return Ok(id.clone());
-        }
+        let challenge = lichess.ai_typed(3, None, None, None).await.unwrap();
+        assert_eq!(challenge.id, "abcd1234");
 
-        panic!("INTERNAL ERROR: something has gone horribly wrong (in client.rs: `fn ai`, line {})\n response: {:?}", line!(), res);
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("POST /api/challenge/ai "));
+        assert_eq!(body(&request), "level=3&color=random");
     }
 
-    /// Create a seek
-    /// Requires `board:play` scope
-    pub async fn seek(
-        &self,
-        rated: bool,
-        color: Option<Color>,
-        clock: ClockSettings,
-        initial: Option<String>,
-    ) -> Response<Option<String>> {
-        let mut body = String::from("{");
+    #[test]
+    fn default_builder_is_new() {
+        let built = Lichess::builder("token").build();
+        let new = Lichess::new("token");
 
-        match color {
-            Some(Color::White) => body.push_str("color=white"),
-            Some(Color::Black) => body.push_str("color=black"),
-            None => body.push_str("color=random"),
-        }
+        assert_eq!(built.key, new.key);
+        assert_eq!(built.auth, new.auth);
+        assert_eq!(built.base_url, "https://lichess.org");
+        assert_eq!(built.base_url, new.base_url);
+        assert_eq!(built.max_retries, new.max_retries);
+        assert_eq!(built.timeout, new.timeout);
+        assert_eq!(built.max_body_size, new.max_body_size);
+        assert_eq!(built.validate_fen, new.validate_fen);
+        assert_eq!(built.granted_scopes.is_some(), new.granted_scopes.is_some());
+        assert_eq!(built.skip_keepalives, new.skip_keepalives);
+        assert_eq!(built.max_line_length, new.max_line_length);
+        assert_eq!(built.on_request.is_some(), new.on_request.is_some());
+        assert_eq!(built.retry_transient, new.retry_transient);
 
-        if rated {
-            body.push_str("&rated=true");
-        }
+        assert_eq!(format!("{built:?}"), format!("{new:?}"));
+        assert!(!format!("{built:?}").contains("token"));
+    }
 
-        if clock.is_correspondence {
-            body.push_str(format!("&days={}", clock.days).as_str());
-        } else {
-            body.push_str(format!("&time={}", clock.limit).as_str());
-            body.push_str(format!("&increment={}", clock.increment).as_str());
-        }
+    #[test]
+    fn challenge_body_defaults() {
+        assert_eq!(
+            ChallengeRequest::new().body().unwrap(),
+            [
+                ("rated", String::from("false")),
+                ("color", String::from("random"))
+            ]
+        );
+    }
 
-        if let Some(fen) = initial {
-            body.push_str(format!("&fen={fen}").as_str());
-        }
+    #[test]
+    fn challenge_body_options() {
+        let body = ChallengeRequest::new()
+            .rated(true)
+            .color(Color::Black)
+            .clock(clock(180, 2))
+            .keep_alive_stream(true)
+            .rule("noAbort")
+            .rule("noRematch")
+            .accept_by_token("their-token")
+            .message("Your game: {game}")
+            .body()
+            .unwrap();
 
-        body.push_str("}\n");
-        let res = self.post_api_raw(String::from("board/seek"), body).await?;
+        assert_eq!(
+            body,
+            [
+                ("rated", String::from("true")),
+                ("color", String::from("black")),
+                ("clock.limit", String::from("180")),
+                ("clock.increment", String::from("2")),
+                ("keepAliveStream", String::from("true")),
+                ("rules", String::from("noAbort,noRematch")),
+                ("acceptByToken", String::from("their-token")),
+                ("message", String::from("Your game: {game}")),
+            ]
+        );
+    }
 
-        if res.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(res))
-        }
+    /// Stream `body` through `ndjson_lines`, as if Lichess had sent it
+    async fn ndjson_values(body: &str, skip_keepalives: bool) -> Vec<Value> {
+        let (url, _requests) = mock(vec![reply("200 OK", "", body)]).await;
+        let res = client(&url).build().open_ndjson(&url).await.unwrap();
+
+        Lichess::ndjson_lines::<Value>(res, skip_keepalives, 1024)
+            .map(Result::unwrap)
+            .collect()
+            .await
     }
 
-    /// Make a move in a game
-    /// Requires `board:play` scope
-    pub async fn make_move(&self, id: &String, m: String, draw: bool) -> Response<bool> {
-        let res = self
-            .post_api(
-                format!("board/game/{id}/move/{m}?offeringDraw={draw}"),
-                String::new(),
-            )
-            .await?;
+    #[tokio::test]
+    async fn ndjson_keepalives() {
+        let body = concat!(
+            r#"{"type":"gameStart","game":{"gameId":"abcd1234"}}"#,
+            "\n{}\n   \n\n {} \n",
+            r#"{"type":"gameFinish","game":{"gameId":"abcd1234"}}"#,
+            "\n",
+        );
+        let start = serde_json::json!({ "type": "gameStart", "game": { "gameId": "abcd1234" } });
+        let finish = serde_json::json!({ "type": "gameFinish", "game": { "gameId": "abcd1234" } });
 
-        if let Value::Object(err) = &res["error"] {
-            return Err(format!("{err:?}").into());
-        }
+        assert_eq!(
+            ndjson_values(body, true).await,
+            [start.clone(), finish.clone()]
+        );
 
-        if let Value::Bool(ok) = &res["ok"] {
-            return Ok(*ok);
+        // blank lines are always dropped, `{}` only when asked to
+        let empty = serde_json::json!({});
+        assert_eq!(
+            ndjson_values(body, false).await,
+            [start, empty.clone(), empty, finish]
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_body_is_null() {
+        let (url, _requests) = mock(vec![reply("200 OK", "", ""), reply("200 OK", "", "")]).await;
+        let lichess = client(&url).build();
+
+        assert_eq!(lichess.get_api("account").await.unwrap(), Value::Null);
+        assert_eq!(
+            lichess.post_api("board/seek", String::new()).await.unwrap(),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn premove_waits_for_its_turn() {
+        let queue = PremoveQueue::new();
+        queue.push("e2e4");
+
+        assert_eq!(queue.due(false, 0, None), None);
+        assert_eq!(queue.due(true, 0, None).as_deref(), Some("e2e4"));
+
+        // it's only taken off the queue once Lichess accepts it
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn premove_sent_once_per_position() {
+        let queue = PremoveQueue::new();
+        queue.push("e2e4");
+
+        assert_eq!(queue.due(true, 0, Some(0)), None);
+        assert_eq!(queue.len(), 1);
+
+        // once the opponent replies, the next premove is due
+        queue.moves.lock().unwrap().pop_front();
+        queue.push("g1f3");
+        assert_eq!(queue.due(true, 2, Some(0)).as_deref(), Some("g1f3"));
+    }
+
+    /// A board stream holding only a `GameFull`, with `me` as white
+    fn game_full(moves: &str) -> String {
+        let full = serde_json::json!({
+            "type": "gameFull",
+            "id": "abcd1234",
+            "variant": { "key": "standard", "name": "Standard" },
+            "speed": "correspondence",
+            "white": { "id": "me", "name": "Me" },
+            "black": { "id": "them", "name": "Them" },
+            "initialFen": "startpos",
+            "state": {
+                "moves": moves,
+                "wtime": 0,
+                "btime": 0,
+                "winc": 0,
+                "binc": 0,
+                "status": "started",
+            },
+        });
+
+        format!("{full}\n")
+    }
+
+    /// Run `queue` on a game where it's white's move after `moves`, with Lichess
+    /// answering the first move sent with `answer`; the stream then ends
+    async fn run_premoves(queue: &PremoveQueue, moves: &str, answer: String) -> Vec<String> {
+        let (url, mut requests) = mock(vec![
+            reply("200 OK", "", r#"{"id":"me","username":"Me"}"#),
+            reply("200 OK", "", &game_full(moves)),
+            answer,
+        ])
+        .await;
+
+        let res = queue.run(&client(&url).build(), "abcd1234").await;
+        assert!(matches!(res, Err(LichessError::Unexpected { .. })));
+
+        let mut seen = Vec::new();
+        while let Ok(request) = requests.try_recv() {
+            seen.push(request);
         }
+        seen
+    }
+
+    #[tokio::test]
+    async fn premove_popped_once_accepted() {
+        let queue = PremoveQueue::new();
+        queue.push("g1f3");
+        queue.push("f1c4");
 
-        panic!(
-            "INTERNAL ERROR: something has gone horribly wrong (in client.rs: `fn ai`, line {})",
-            line!()
+        let requests =
+            run_premoves(&queue, "e2e4 e7e5", reply("200 OK", "", r#"{"ok":true}"#)).await;
+        assert!(requests[2].starts_with("POST /api/board/game/abcd1234/move/g1f3?"));
+        assert_eq!(
+            queue.moves.lock().unwrap().front().map(String::as_str),
+            Some("f1c4")
         );
     }
 
-    /// Resign a game
-    /// Requires `board:play` scope
-    pub async fn resign(&self, id: String) -> Response<bool> {
-        let res = self
-            .post_api(format!("board/game/{id}/resign"), String::new())
-            .await?;
+    #[tokio::test]
+    async fn illegal_premove_cancels_queue() {
+        let queue = PremoveQueue::new();
+        queue.push("e4e5");
+        queue.push("g1f3");
 
-        if let Value::Object(err) = &res["error"] {
-            return Err(format!("{err:?}").into());
+        // e7e5 blocks the pawn, so Lichess refuses it and neither is played
+        let illegal = reply(
+            "400 Bad Request",
+            "",
+            r#"{"error":"Piece on e4 cannot move to e5"}"#,
+        );
+        let requests = run_premoves(&queue, "e2e4 e7e5", illegal).await;
+        assert_eq!(requests.len(), 3);
+        assert!(requests[2].starts_with("POST /api/board/game/abcd1234/move/e4e5?"));
+        assert!(queue.is_empty());
+    }
+
+    #[tokio::test]
+    async fn transient_retry() {
+        // the first reply comes too late, so that attempt times out
+        let (url, mut requests) = mock_delayed(vec![
+            (Duration::from_secs(2), reply("200 OK", "", "late")),
+            (Duration::ZERO, reply("200 OK", "", "1. e4 e5")),
+        ])
+        .await;
+        let lichess = client(&url)
+            .timeout(Duration::from_millis(200))
+            .retry_transient(true)
+            .build();
+
+        let res = lichess
+            .get_raw_accept(
+                &lichess.api_url("game/export/abcd1234"),
+                "application/x-chess-pgn",
+            )
+            .await;
+        assert_eq!(res.unwrap(), "1. e4 e5");
+
+        for _ in 0..2 {
+            let request = requests.recv().await.unwrap().to_lowercase();
+            assert!(request.contains("\r\naccept: application/x-chess-pgn\r\n"));
+        }
+    }
+
+    #[tokio::test]
+    async fn transient_errors_without_retry() {
+        let (url, mut requests) = mock_delayed(vec![
+            (Duration::from_secs(2), reply("200 OK", "", "late")),
+            (Duration::ZERO, reply("200 OK", "", "1. e4 e5")),
+        ])
+        .await;
+        let lichess = client(&url).timeout(Duration::from_millis(200)).build();
+
+        let res = lichess
+            .get_raw(&lichess.api_url("game/export/abcd1234"))
+            .await;
+        assert!(matches!(res, Err(LichessError::Transport(e)) if e.is_timeout()));
+
+        requests.recv().await.unwrap();
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[test]
+    fn fen_accepted() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2",
+            "4k3/8/8/8/8/8/8/4K3 b - -",
+            // chess960 castling files
+            "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEge - 0 1",
+        ] {
+            assert_eq!(Lichess::validate_fen(fen), Ok(()), "{fen}");
         }
+    }
 
-        if let Value::Bool(ok) = &res["ok"] {
-            return Ok(*ok);
+    #[test]
+    fn fen_rejected() {
+        for (fen, error) in [
+            ("", "expected 4 to 6 fields"),
+            ("8/8/8/8/8/8/8/8 w", "expected 4 to 6 fields"),
+            ("4k3/8/8/8/8/8/4K3 w - - 0 1", "expected 8 ranks"),
+            (
+                "4k3/8/8/8/8/8/8/4K2 w - - 0 1",
+                "expected 8 squares in every rank",
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K4 w - - 0 1",
+                "expected 8 squares in every rank",
+            ),
+            ("4k3/8/8/8/8/8/8/4X3 w - - 0 1", "unknown piece"),
+            ("8/8/8/8/8/8/8/4K3 w - - 0 1", "expected one king per side"),
+            (
+                "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+                "side to move must be `w` or `b`",
+            ),
+            ("4k3/8/8/8/8/8/8/4K3 w KX - 0 1", "invalid castling rights"),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e4 0 1",
+                "invalid en passant square",
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - x 1",
+                "move counters must be numbers",
+            ),
+            // three-check counters, and crazyhouse pockets
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 3+3 0 1",
+                "expected 4 to 6 fields",
+            ),
+            ("4k3/8/8/8/8/8/8/4K3[Qq] w - - 0 1", "unknown piece"),
+        ] {
+            assert_eq!(Lichess::validate_fen(fen), Err(error), "{fen}");
         }
+    }
 
-        panic!(
-            "INTERNAL ERROR: something has gone horribly wrong (in client.rs: `fn ai`, line {})",
-            line!()
-        );
+    fn board_event(value: Value) -> Option<BoardEvent> {
+        Some(serde_json::from_value(value).unwrap())
     }
 
-    /// Get a stream from a server
-    pub async fn stream(&self, url: String) -> Response<impl Stream<Item = String>> {
-        let res = self
-            .hclient
-            .get(url)
-            .bearer_auth(self.key.clone())
-            .send()
-            .await?
-            .bytes_stream();
-
-        Ok(Box::pin(
-            LinesStream::new(StreamReader::new(res.map_err(Lichess::convert_err)).lines())
-                .filter_map(|l| async move {
-                    let line = l.ok()?;
-                    if line.is_empty() {
-                        None
-                    } else {
-                        Some(line)
-                    }
-                }),
-        ))
+    #[test]
+    fn game_tracker_turns() {
+        let mut game = GameTracker {
+            me: String::from("me"),
+            sides: None,
+        };
+        let full: Value = serde_json::from_str(&game_full("e2e4 e7e5")).unwrap();
+
+        // nothing is known about the sides before the `GameFull`
+        let mut update = full["state"].clone();
+        update["type"] = Value::from("gameState");
+        let state = game.update(board_event(update)).unwrap().unwrap();
+        assert!(!game.my_turn(&state));
+
+        let state = game.update(board_event(full.clone())).unwrap().unwrap();
+        assert!(game.my_turn(&state));
+        assert!(!GameTracker::ended(&state));
+
+        let mut state = state;
+        state.moves.push_str(" g1f3");
+        assert!(!game.my_turn(&state));
+
+        // black moving first flips the parity
+        let mut full = full;
+        full["initialFen"] = Value::from("4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+        full["white"]["id"] = Value::from("them");
+        full["black"]["id"] = Value::from("me");
+        full["state"]["moves"] = Value::from("");
+        let state = game.update(board_event(full)).unwrap().unwrap();
+        assert!(game.my_turn(&state));
+
+        assert!(matches!(
+            game.update(None),
+            Err(LichessError::Unexpected { .. })
+        ));
     }
 
-    /// Get an ndjson stream from a server
-    pub async fn ndjson<T: DeserializeOwned>(
-        &self,
-        url: String,
-    ) -> Response<impl Stream<Item = T>> {
-        let res = self
-            .hclient
-            .get(url)
-            .bearer_auth(self.key.clone())
-            .send()
-            .await?
-            .bytes_stream();
-
-        Ok(Box::pin(
-            LinesStream::new(StreamReader::new(res.map_err(Lichess::convert_err)).lines())
-                .filter_map(|l| async move {
-                    let line = l.ok()?;
-                    if line.is_empty() {
-                        None
-                    } else {
-                        serde_json::from_str(line.as_str()).ok()?
+    #[test]
+    fn winner_color() {
+        let state = serde_json::json!({
+            "moves": "f2f3 e7e5 g2g4 d8h4",
+            "wtime": 0,
+            "btime": 0,
+            "winc": 0,
+            "binc": 0,
+            "status": "mate",
+            "winner": "black",
+        });
+        let state: GameState = serde_json::from_value(state).unwrap();
+        assert_eq!(state.winner, Some(Color::Black));
+
+        let drawn: GameState = serde_json::from_value(serde_json::json!({
+            "moves": "",
+            "wtime": 0,
+            "btime": 0,
+            "winc": 0,
+            "binc": 0,
+            "status": "draw",
+        }))
+        .unwrap();
+        assert!(drawn.winner.is_none());
+    }
+
+    #[tokio::test]
+    async fn raw_request_leaves_errors() {
+        let (url, mut requests) =
+            mock(vec![reply("404 Not Found", "", r#"{"error":"Not found"}"#)]).await;
+        let lichess = client(&url).build();
+
+        let res = lichess
+            .raw_request(reqwest::Method::GET, "study/abcd1234", None)
+            .await
+            .unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::NOT_FOUND);
+        assert!(requests
+            .recv()
+            .await
+            .unwrap()
+            .starts_with("GET /api/study/abcd1234 "));
+    }
+
+    #[tokio::test]
+    async fn bearer_header() {
+        let (url, mut requests) = mock(vec![reply("200 OK", "", "{}")]).await;
+        let lichess = client(&url).build();
+        assert!(lichess.auth.as_ref().unwrap().is_sensitive());
+
+        lichess.get_api("account").await.unwrap();
+        let request = requests.recv().await.unwrap();
+        assert!(request.contains("\r\nauthorization: Bearer token\r\n"));
+    }
+
+    #[tokio::test]
+    async fn no_bearer_header_without_key() {
+        let (url, mut requests) = mock(vec![reply("200 OK", "", "{}")]).await;
+        let hclient = reqwest::Client::builder().no_proxy().build().unwrap();
+        let lichess = Lichess::builder("").base_url(url).client(hclient).build();
+        assert!(lichess.auth.is_none());
+
+        lichess.get_api("puzzle/daily").await.unwrap();
+        let request = requests.recv().await.unwrap().to_lowercase();
+        assert!(!request.contains("\r\nauthorization:"));
+    }
+
+    #[tokio::test]
+    async fn transient_retry_after_refused_connection() {
+        use std::io::{Read, Write};
+
+        // nothing listens on the port at first, so the first attempt is refused
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let lichess = client(&format!("http://127.0.0.1:{port}"))
+            .retry_transient(true)
+            .on_request(move |metric| {
+                if metric.status.is_some() {
+                    return;
+                }
+
+                // start listening after the refused attempt, before the retry
+                let listener = std::net::TcpListener::bind(("127.0.0.1", port)).unwrap();
+                std::thread::spawn(move || {
+                    let (mut conn, _) = listener.accept().unwrap();
+                    let mut request = Vec::new();
+                    let mut chunk = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match conn.read(&mut chunk) {
+                            Ok(0) | Err(_) => break,
+                            Ok(n) => request.extend_from_slice(&chunk[..n]),
+                        }
                     }
-                }),
-        ))
+
+                    let _ = conn.write_all(reply("200 OK", "", "{}").as_bytes());
+                });
+            })
+            .build();
+
+        let res = lichess.get_raw(&lichess.api_url("account")).await;
+        assert_eq!(res.unwrap(), "{}");
     }
 
-    /// Get a listener to the user events stream
-    /// Requires `challenge:read bot:play board:play` scopes
-    pub async fn events<T: DeserializeOwned>(&self) -> Response<impl Stream<Item = T>> {
-        self.ndjson("https://lichess.org/api/stream/event".to_string())
+    #[tokio::test]
+    async fn stream_helpers_take_str() {
+        let (url, mut requests) = mock(vec![
+            reply("200 OK", "", "one\ntwo\n"),
+            reply("200 OK", "", "{\"n\":1}\n{}\n"),
+            reply("200 OK", "", "three\n"),
+        ])
+        .await;
+        let lichess = client(&url).build();
+
+        // the streams don't borrow the url, so it can be a temporary
+        let lines = lichess.stream(&format!("{url}/api/lines")).await.unwrap();
+        let values = lichess
+            .ndjson::<Value>(&format!("{url}/api/values"))
+            .await
+            .unwrap();
+        let posted = lichess
+            .post_stream(&format!("{url}/api/posted"), String::from("a,b"))
+            .await
+            .unwrap();
+
+        assert_eq!(lines.collect::<Vec<_>>().await, ["one", "two"]);
+        assert_eq!(
+            values.collect::<Vec<_>>().await,
+            [serde_json::json!({ "n": 1 })]
+        );
+        assert_eq!(posted.collect::<Vec<_>>().await, ["three"]);
+
+        assert!(requests
+            .recv()
+            .await
+            .unwrap()
+            .starts_with("GET /api/lines "));
+        assert!(requests
+            .recv()
             .await
+            .unwrap()
+            .starts_with("GET /api/values "));
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("POST /api/posted "));
+        assert_eq!(body(&request), "a,b");
     }
 
-    /// Get a listener to a board event stream
-    /// Requires `board:play` scopre
-    pub async fn board<T: DeserializeOwned>(&self, id: &String) -> Response<impl Stream<Item = T>> {
-        self.ndjson(format!("https://lichess.org/api/board/game/stream/{id}"))
+    #[test]
+    fn encode_path_escapes() {
+        assert_eq!(Lichess::encode_path("DrNykterstein"), "DrNykterstein");
+        assert_eq!(Lichess::encode_path("a b/c?d#e"), "a%20b%2Fc%3Fd%23e");
+    }
+
+    #[tokio::test]
+    async fn path_segments_are_encoded() {
+        let (url, mut requests) = mock(vec![reply("200 OK", "", "[]")]).await;
+        let lichess = client(&url).build();
+
+        lichess.activity("we/ird?x").await.unwrap();
+        assert!(requests
+            .recv()
             .await
+            .unwrap()
+            .starts_with("GET /api/user/we%2Fird%3Fx/activity "));
     }
 
-    // TODO: consider other ErrorKind's
-    fn convert_err(e: reqwest::Error) -> std::io::Error {
-        std::io::Error::new(std::io::ErrorKind::Other, e)
+    #[tokio::test]
+    async fn token_info_invalid_token() {
+        let (url, mut requests) = mock(vec![reply("200 OK", "", r#"{"token":null}"#)]).await;
+        let lichess = client(&url).build();
+
+        let res = lichess.token_info().await;
+        assert!(matches!(res, Err(LichessError::InvalidToken)));
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("POST /api/token/test "));
+        assert!(request.contains("\r\ncontent-type: text/plain\r\n"));
+        assert_eq!(body(&request), "token");
     }
 }